@@ -0,0 +1,56 @@
+use image::{ImageBuffer, Rgba};
+
+/// Averages renders of a static view taken at different sub-pixel offsets, so edges get smoother the longer
+/// the view is left alone. Anything that changes the view must start a new accumulator.
+pub struct Accumulator {
+    sum: Vec<f32>, // Running total of every channel of every pixel
+    width: u32,
+    height: u32,
+    pub samples: u32,
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        Accumulator {
+            sum: vec![0.; width as usize * height as usize * 4],
+            width,
+            height,
+            samples: 0,
+        }
+    }
+
+    /// Offset in pixels, from -0.5 to 0.5, to render the next sample at. Taken from the Halton (2, 3)
+    /// sequence so the samples spread evenly over the pixel however many are taken, and never repeat.
+    pub fn next_jitter(&self) -> [f32; 2] {
+        let index = self.samples + 1; // Index 0 is the pixel's corner in both bases
+        [halton(index, 2) - 0.5, halton(index, 3) - 0.5]
+    }
+
+    /// Add a sample. It must be the same size as the accumulator.
+    pub fn add(&mut self, img: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
+        for (total, &channel) in self.sum.iter_mut().zip(img.as_raw()) {
+            *total += channel as f32;
+        }
+        self.samples += 1;
+    }
+
+    /// The average of every sample so far.
+    pub fn average(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let samples = self.samples.max(1) as f32;
+        let channels = self.sum.iter().map(|total| (total / samples).round() as u8);
+        ImageBuffer::from_raw(self.width, self.height, channels.collect())
+            .expect("accumulator holds 4 channels per pixel")
+    }
+}
+
+/// Element `index` of the Halton sequence in `base`, between 0 and 1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.;
+    let mut fraction = 1.;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
@@ -0,0 +1,86 @@
+use crate::mandelbrot_settings::MandelbrotSettings;
+use crate::parallel::*;
+use crate::render::{count_point, pixel_to_complex, PixelClass, ViewTransform};
+
+/// How much of the view is in the set, going by how many samples stayed bounded up to the iteration limit.
+/// Either counted from a render's own counts, which costs nothing, or from points scattered at random over the
+/// view, which gives an honest error bar. Both overestimate a little at low limits, since points that escape
+/// only after `max_iterations` are counted as inside.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaEstimate {
+    pub interior: usize, // Samples that reached the iteration limit
+    pub samples: usize, // Samples looked at, leaving out degenerate ones, whose counts mean nothing
+}
+
+impl AreaEstimate {
+    /// Count the interior samples in the iteration counts of a render, such as from `render_counts`. With
+    /// supersampling every sample counts, so the estimate is finer than the image.
+    pub fn from_counts(counts: &[f32], settings: &MandelbrotSettings) -> AreaEstimate {
+        let mut estimate = AreaEstimate {
+            interior: 0,
+            samples: 0,
+        };
+        for &count in counts {
+            match PixelClass::of(count, settings) {
+                PixelClass::Interior => {
+                    estimate.interior += 1;
+                    estimate.samples += 1;
+                }
+                PixelClass::Escaped => estimate.samples += 1,
+                PixelClass::Degenerate => {}
+            }
+        }
+        estimate
+    }
+
+    /// Iterate `samples` points placed uniformly at random over the view. A pixel grid lines its samples up with
+    /// the set's straight edges and symmetry, so its error is hard to judge; random points have the plain
+    /// binomial error of `standard_error`, which shrinks with the square root of the sample count.
+    /// The points come from a generator seeded with `seed`, so the same seed gives the same estimate.
+    pub fn monte_carlo(settings: &MandelbrotSettings, samples: usize, seed: u32) -> AreaEstimate {
+        // xorshift32, as for the Poisson sample pattern. It gets stuck at 0, so that seed is nudged off it
+        let mut state: u32 = seed.wrapping_add(0x9e37_79b9).max(1);
+        let mut random = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state >> 8) as f32 / (1 << 24) as f32
+        };
+        let [width, height] = [settings.width as f32, settings.height as f32];
+        let points: Vec<[f32; 2]> = (0..samples)
+            .map(|_| [random() * width, random() * height])
+            .collect();
+        let counts: Vec<f32> = points
+            .par_iter()
+            .map(|&[x, y]| {
+                count_point(
+                    pixel_to_complex(settings, x, y),
+                    settings,
+                    settings.max_iterations,
+                )
+            })
+            .collect();
+        AreaEstimate::from_counts(&counts, settings)
+    }
+
+    /// Share of the samples in the set, from 0 to 1. `None` if there were none to look at.
+    pub fn fraction(&self) -> Option<f64> {
+        (self.samples > 0).then(|| self.interior as f64 / self.samples as f64)
+    }
+
+    /// Standard error of `fraction` for samples taken at random, √(p (1 - p) / n).
+    pub fn standard_error(&self) -> Option<f64> {
+        let p = self.fraction()?;
+        Some((p * (1. - p) / self.samples as f64).sqrt())
+    }
+
+    /// The interior's area in the complex plane: `fraction` of the rectangle in view. Only the linear view
+    /// shows a rectangle of the plane with every part the same size, so the others give `None`.
+    pub fn area(&self, settings: &MandelbrotSettings) -> Option<f64> {
+        if settings.view_transform != ViewTransform::Linear {
+            return None;
+        }
+        let view = settings.world_width() as f64 * settings.world_height() as f64;
+        Some(self.fraction()? * view)
+    }
+}
@@ -0,0 +1,110 @@
+use crate::mandelbrot_settings::MandelbrotSettings;
+use crate::render::render_with_counts_cancellable;
+use image::{ImageBuffer, Rgba};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// A finished background render: the image and the iteration counts it was coloured from, as returned by
+/// `render_with_counts`.
+pub struct RenderOutput {
+    pub counts: Vec<f32>,
+    pub image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+}
+
+/// State shared between a handle and its render thread.
+struct Shared {
+    slot: Mutex<Slot>,
+    finished: Condvar, // Signalled once the slot is filled or the render gives up
+    cancelled: AtomicBool,
+}
+
+#[derive(Default)]
+struct Slot {
+    done: bool,
+    output: Option<RenderOutput>, // `None` once done means cancelled
+    waker: Option<Waker>,         // Task to wake when done, if the handle is being awaited
+}
+
+/// A render running on its own thread, started by `spawn_render`. Check `poll_output` once per frame from an
+/// event loop, block with `wait`, or `.await` it. Cancelling, or dropping the handle, stops the render soon after,
+/// so a render superseded by newer input doesn't keep the CPU busy.
+pub struct RenderHandle {
+    shared: Arc<Shared>,
+}
+
+/// Start rendering `settings` on a new thread. The rows are still spread over rayon's pool, as in `render`.
+pub fn spawn_render(settings: MandelbrotSettings) -> RenderHandle {
+    let shared = Arc::new(Shared {
+        slot: Mutex::default(),
+        finished: Condvar::new(),
+        cancelled: AtomicBool::new(false),
+    });
+    let worker = Arc::clone(&shared);
+    std::thread::spawn(move || {
+        let output = render_with_counts_cancellable(&settings, &worker.cancelled)
+            .map(|(counts, image)| RenderOutput { counts, image });
+        let mut slot = worker.slot.lock().unwrap();
+        slot.done = true;
+        slot.output = output;
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+        worker.finished.notify_all();
+    });
+    RenderHandle { shared }
+}
+
+impl RenderHandle {
+    /// Ask the render to stop. Its output is discarded even if it had already finished.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the render has finished or given up, so `poll_output` won't return `None` for lack of time.
+    pub fn is_finished(&self) -> bool {
+        self.shared.slot.lock().unwrap().done
+    }
+
+    /// Take the output if the render has finished, without blocking. Returns it only once.
+    pub fn poll_output(&self) -> Option<RenderOutput> {
+        if self.shared.cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.shared.slot.lock().unwrap().output.take()
+    }
+
+    /// Block until the render finishes. `None` if it was cancelled.
+    pub fn wait(self) -> Option<RenderOutput> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        while !slot.done {
+            slot = self.shared.finished.wait(slot).unwrap();
+        }
+        let output = slot.output.take();
+        output.filter(|_| !self.shared.cancelled.load(Ordering::Relaxed))
+    }
+}
+
+impl Future for RenderHandle {
+    type Output = Option<RenderOutput>; // `None` if cancelled
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        if slot.done {
+            let output = slot.output.take();
+            Poll::Ready(output.filter(|_| !self.shared.cancelled.load(Ordering::Relaxed)))
+        } else {
+            slot.waker = Some(context.waker().clone()); // Replacing any waker from an earlier poll
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for RenderHandle {
+    /// Nobody can collect the output any more, so stop working on it.
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -0,0 +1,80 @@
+use crate::mandelbrot_settings::MandelbrotSettings;
+use std::time::Duration;
+
+/// Most the resolution is divided by to meet the budget. Past it the iterations are divided too.
+pub const MAX_BUDGET_SCALE: f32 = 8.;
+/// Most the iterations are divided by on top of that, so the quality can't fall without limit.
+pub const MAX_BUDGET_ITERATION_SCALE: f32 = 16.;
+/// Frames taking between this share of the budget and all of it leave the quality alone. The gap keeps the
+/// small differences between frames from nudging it back and forth.
+const BUDGET_DEADBAND: f32 = 0.6;
+/// How far towards the quality that would exactly hit the middle of the deadband each frame moves, on a log
+/// scale. Less than a full step, so a frame that's slow for other reasons, or a poor guess at how time scales,
+/// can't make it overshoot and swing back.
+const BUDGET_GAIN: f32 = 0.5;
+
+/// Picks the quality of quick renders so each takes about `target`, however deep the view, for a steady frame
+/// rate while navigating on slow hardware. Call `settings_for` for the settings of the next quick render and
+/// `record` with how long it took. Slow frames lower the resolution (and, once that's down to 1/8, the
+/// iterations), and fast ones raise it back up, towards the full quality when the view is cheap enough.
+///
+/// The quality is one number, the factor the resolution is divided by, carrying on into dividing the iterations
+/// past `MAX_BUDGET_SCALE`. Render time goes roughly with the pixel count, the inverse square of the resolution
+/// factor, and with the iterations, so each frame steps it by part of the square root of how far off the time
+/// was, which settles without oscillating.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameBudget {
+    target: Duration,
+    quality: f32, // From 1, full quality, up to MAX_BUDGET_SCALE * MAX_BUDGET_ITERATION_SCALE
+}
+
+impl FrameBudget {
+    /// A budget of `target` per frame, starting from dividing the resolution by `initial_scale`.
+    pub fn new(target: Duration, initial_scale: f32) -> FrameBudget {
+        FrameBudget {
+            target,
+            quality: initial_scale.clamp(1., MAX_BUDGET_SCALE * MAX_BUDGET_ITERATION_SCALE),
+        }
+    }
+
+    /// How much the resolution is currently divided by, from 1 to `MAX_BUDGET_SCALE`.
+    pub fn scale(&self) -> f32 {
+        self.quality.min(MAX_BUDGET_SCALE)
+    }
+
+    /// How much the iterations are currently divided by, 1 until the resolution is at its lowest.
+    pub fn iteration_scale(&self) -> f32 {
+        (self.quality / MAX_BUDGET_SCALE).max(1.)
+    }
+
+    /// Cheaper settings for the same view, at the current quality. Like `MandelbrotSettings::preview`, the
+    /// iterations start from `navigation_iterations` if that's set.
+    pub fn settings_for(&self, settings: &MandelbrotSettings) -> MandelbrotSettings {
+        let shrink = |size: u32, scale: f32| ((size as f32 / scale).round() as u32).max(1);
+        let iterations = match settings.navigation_iterations {
+            Some(cap) => cap.min(settings.max_iterations),
+            None => settings.max_iterations,
+        };
+        MandelbrotSettings {
+            width: shrink(settings.width, self.scale()),
+            height: shrink(settings.height, self.scale()),
+            max_iterations: shrink(iterations, self.iteration_scale()),
+            supersample: 1,
+            ..settings.clone()
+        }
+    }
+
+    /// Adjust the quality after a render at it took `elapsed`.
+    pub fn record(&mut self, elapsed: Duration) {
+        let ratio = elapsed.as_secs_f32() / self.target.as_secs_f32();
+        if (BUDGET_DEADBAND..=1.).contains(&ratio) {
+            return;
+        }
+        // Aim for the middle of the deadband. At most halve or double the quality in one frame, so a frame
+        // that took no measurable time doesn't jump straight to full quality
+        let aim = (BUDGET_DEADBAND + 1.) / 2.;
+        let step = (ratio / aim).sqrt().powf(BUDGET_GAIN).clamp(0.5, 2.);
+        self.quality =
+            (self.quality * step).clamp(1., MAX_BUDGET_SCALE * MAX_BUDGET_ITERATION_SCALE);
+    }
+}
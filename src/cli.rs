@@ -0,0 +1,1053 @@
+use mandelbrot_test::fractal::FractalKind;
+use mandelbrot_test::mandelbrot_settings::{
+    load_importance_or_warn, load_palette_or_warn, MandelbrotSettings, MAX_BAILOUT, MIN_BAILOUT,
+};
+use mandelbrot_test::render::{
+    ColourMode, DownscaleFilter, EscapeShape, InteriorMode, Precision, Region, SamplePattern,
+    Transparency, ViewTransform, MAX_SMOOTH_EXTRA_ITERATIONS,
+};
+use mandelbrot_test::symmetry::Symmetry;
+use num_complex::Complex;
+use std::path::PathBuf;
+
+/// Where `--resume` looks for the session without `--session`.
+const DEFAULT_SESSION: &str = "mandelbrot_session.json";
+/// Allowed double click timeouts in milliseconds. Shorter is nearly impossible to hit, longer makes single clicks zoom.
+const DOUBLE_CLICK_RANGE: std::ops::RangeInclusive<u64> = 100..=2000;
+
+/// Modes chosen by the first argument, each with its own flags on top of the view and colouring flags they all
+/// share. Without one the window opens, unless a headless flag such as `--output` is given, as before subcommands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subcommand {
+    View,    // The interactive window
+    Render,  // One image, or a directory of tiles
+    Animate, // A zoom sequence of numbered frames
+    Batch,   // Every view listed in a file
+    Diff,    // How far the counts of two settings files differ
+    Montage, // Thumbnails of several settings files on one sheet
+}
+
+impl Subcommand {
+    pub const ALL: [Subcommand; 6] = [
+        Subcommand::View,
+        Subcommand::Render,
+        Subcommand::Animate,
+        Subcommand::Batch,
+        Subcommand::Diff,
+        Subcommand::Montage,
+    ];
+
+    /// Name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Subcommand::View => "view",
+            Subcommand::Render => "render",
+            Subcommand::Animate => "animate",
+            Subcommand::Batch => "batch",
+            Subcommand::Diff => "diff",
+            Subcommand::Montage => "montage",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Subcommand> {
+        Subcommand::ALL.into_iter().find(|sub| sub.name() == name)
+    }
+
+    /// One line description for `--help`.
+    fn summary(self) -> &'static str {
+        match self {
+            Subcommand::View => "Open the interactive viewer (the default)",
+            Subcommand::Render => "Render one image with --output, or tiles with --tiles-dir",
+            Subcommand::Animate => {
+                "Render a zoom towards the centre, or a --tour, as numbered frames in --frames-dir"
+            }
+            Subcommand::Batch => "Render every view listed in a file (see --batch in the README)",
+            Subcommand::Diff => "Compare the iteration counts of two settings files",
+            Subcommand::Montage => "Lay several settings files out as thumbnails on one sheet",
+        }
+    }
+
+    /// Arguments shown after the name in `--help`.
+    fn arguments(self) -> &'static str {
+        match self {
+            Subcommand::Batch => "<views.txt> [flags]",
+            Subcommand::Diff => "<a.json> <b.json> [flags]",
+            Subcommand::Montage => "<view.json>... --output <sheet.png> [flags]",
+            _ => "[flags]",
+        }
+    }
+
+    /// Flags only this subcommand takes. Flags not listed for any subcommand are shared by all of them.
+    fn own_flags(self) -> &'static [&'static str] {
+        match self {
+            Subcommand::View => &[
+                "--session",
+                "--resume",
+                "--commands",
+                "--timings",
+                "--snap-clicks",
+                "--no-inertia",
+                "--frames-dir",
+                "--sidecar",
+                "--16-bit",
+                "--symmetry",
+                "--background",
+                "--double-click-ms",
+                "--preview-scale",
+                "--frame-budget-ms",
+                "--crossfade-ms",
+                "--recolour-fade-ms",
+                "--heatmap-opacity",
+                "--tour",
+            ],
+            Subcommand::Render => &[
+                "--output",
+                "--region",
+                "--tiles-dir",
+                "--tile-size",
+                "--sidecar",
+                "--16-bit",
+                "--symmetry",
+            ],
+            Subcommand::Animate => &["--frames-dir", "--frames", "--tour", "--symmetry"],
+            Subcommand::Batch => &[
+                "--batch",
+                "--batch-dir",
+                "--sidecar",
+                "--16-bit",
+                "--symmetry",
+            ],
+            Subcommand::Diff => &["--output"],
+            Subcommand::Montage => &["--output", "--columns", "--cell"],
+        }
+    }
+
+    /// Whether the arguments that aren't flags are settings files to render, rather than mistakes.
+    fn takes_files(self) -> bool {
+        matches!(self, Subcommand::Diff | Subcommand::Montage)
+    }
+
+    /// Reject a flag that belongs to other subcommands only, pointing at the subcommands that take it.
+    fn check_flag(self, flag: &str) -> Result<(), String> {
+        let owners: Vec<&str> = Subcommand::ALL
+            .into_iter()
+            .filter(|sub| sub.own_flags().contains(&flag))
+            .map(Subcommand::name)
+            .collect();
+        if owners.is_empty() || owners.contains(&self.name()) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} isn't a flag of '{}', it's for: {}",
+                flag,
+                self.name(),
+                owners.join(", ")
+            ))
+        }
+    }
+}
+
+/// Flags every subcommand takes, setting up the view and its colouring. Listed for `--help`.
+const SHARED_FLAGS: [&str; 40] = [
+    "--settings",
+    "--print-settings",
+    "--fractal",
+    "--julia-c",
+    "--z0",
+    "--view",
+    "--width",
+    "--height",
+    "--zoom",
+    "--offset-x",
+    "--offset-y",
+    "--y-stretch",
+    "--max-iterations",
+    "--navigation-iterations",
+    "--importance",
+    "--fast-escape",
+    "--escape-shape",
+    "--bailout",
+    "--supersample",
+    "--downscale-filter",
+    "--sample-pattern",
+    "--sample-seed",
+    "--precision",
+    "--f64-region",
+    "--gamma",
+    "--exterior-lift",
+    "--log-colour",
+    "--colour-mode",
+    "--smooth-extra-iterations",
+    "--colour-period",
+    "--colour-offset",
+    "--contours",
+    "--edge-blend",
+    "--palette",
+    "--blend-palette",
+    "--palette-blend",
+    "--interior-mode",
+    "--interior-colour",
+    "--degenerate-colour",
+    "--transparency",
+];
+
+/// Text for `--help`, for one subcommand or for the program as a whole.
+pub fn usage(subcommand: Option<Subcommand>) -> String {
+    let list = |flags: &[&str]| {
+        flags
+            .chunks(6)
+            .map(|line| format!("  {}", line.join(" ")))
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    match subcommand {
+        Some(sub) => format!(
+            "usage: mandelbrot_test {} {}\n{}\n\nFlags:\n{}\n\nView and colouring flags:\n{}",
+            sub.name(),
+            sub.arguments(),
+            sub.summary(),
+            list(sub.own_flags()),
+            list(&SHARED_FLAGS)
+        ),
+        None => {
+            let mut text =
+                "usage: mandelbrot_test [subcommand] [flags]\n\nSubcommands:\n".to_string();
+            for sub in Subcommand::ALL {
+                text += &format!("  {:<8} {}\n", sub.name(), sub.summary());
+            }
+            text += "\nRun 'mandelbrot_test <subcommand> --help' for its flags.";
+            text
+        }
+    }
+}
+
+/// Options read from the command line.
+pub struct CliOptions {
+    pub fractal: Option<FractalKind>,
+    pub view_transform: Option<ViewTransform>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub max_iterations: Option<u32>,
+    pub navigation_iterations: Option<u32>,
+    pub importance: Option<PathBuf>, // Grayscale image scaling the iterations across the view
+    pub gamma: Option<f32>,
+    pub exterior_lift: Option<f32>,
+    pub zoom: Option<f32>,
+    pub y_stretch: Option<f32>,
+    pub z0: Option<Option<Complex<f32>>>, // `Some(None)` for `--z0 c`
+    pub julia_c: Option<Complex<f32>>,
+    pub offset_x: Option<f32>,
+    pub offset_y: Option<f32>,
+    pub supersample: Option<u32>,
+    pub downscale_filter: Option<DownscaleFilter>,
+    pub sample_pattern: Option<SamplePattern>,
+    pub sample_seed: Option<u32>,
+    pub precision: Option<Precision>,
+    pub f64_region: Option<Region>, // Pixels the hybrid precision always iterates in f64
+    pub palette: Option<PathBuf>,   // Gradient strip image to colour with
+    pub blend_palette: Option<PathBuf>, // Second gradient strip mixed into the colouring
+    pub palette_blend: Option<f32>,
+    pub colour_period: Option<f32>,
+    pub colour_offset: Option<f32>,
+    pub interior_mode: Option<InteriorMode>,
+    pub interior_colour: Option<[u8; 4]>,
+    pub degenerate_colour: Option<[u8; 4]>,
+    pub transparency: Option<Transparency>,
+    pub settings: Option<PathBuf>, // Start from settings saved in a JSON sidecar
+    pub session: Option<PathBuf>,  // Where the viewer's state is saved on exit
+    pub resume: bool,              // Start from the state saved in the session file by the last run
+    pub output: Option<PathBuf>,   // Render headlessly to this file instead of opening a window
+    pub region: Option<Region>,    // Only render this part of the view to `output`
+    pub tiles_dir: Option<PathBuf>, // Render headlessly as separate tile images in this directory
+    pub tile_size: u32,            // Width and height of each tile in `tiles_dir`
+    pub batch: Option<PathBuf>,    // Render headlessly every view listed in this file
+    pub batch_dir: PathBuf,        // Directory the `batch` renders are saved in
+    pub files: Vec<PathBuf>, // Settings files `diff` and `montage` render, before the flags are applied
+    pub columns: Option<u32>, // Thumbnails across a `montage` sheet. `None` makes it roughly square
+    pub cell: (u32, u32),    // Width and height of each `montage` thumbnail
+    pub escape_shape: Option<EscapeShape>, // Region a point must leave to escape. `--fast-escape` is the diamond
+    pub bailout: Option<f32>,
+    pub contours: bool,   // Darken the boundaries between iteration bands
+    pub edge_blend: bool, // Soften the set's outline
+    pub colour_mode: Option<ColourMode>,
+    pub smooth_extra_iterations: Option<u32>,
+    pub log_colour: bool,             // Index the ramp by the log of the count
+    pub sidecar: bool, // Write the settings to `<image>.json` next to each saved image
+    pub print_settings: bool, // Print the settings as JSON and exit instead of rendering
+    pub sixteen_bit: bool, // Save images with 16 bits per channel instead of 8
+    pub symmetry: Symmetry, // Fold saved images onto themselves, for wallpapers
+    pub commands: bool, // Read commands from stdin while the window is open
+    pub timings: bool, // Print how long each new image took to render, upload and draw
+    pub snap_clicks: bool, // Recentre on the clicked pixel's coordinate, not the exact cursor position
+    pub no_inertia: bool,  // Stop a dragged view dead on release instead of letting it glide
+    pub double_click_ms: Option<u64>, // Longest gap between clicks that still counts as a double click
+    pub frames_dir: Option<PathBuf>, // Save every auto-zoom frame as a numbered PNG in this directory
+    pub frames: Option<u32>,         // Number of frames `animate` renders
+    pub tour: Option<PathBuf>, // Waypoints of a fly-through, edited by the viewer and flown by `animate`
+    pub background: [f32; 4],  // Colour of any part of the window the image doesn't cover
+    pub preview_scale: u32, // Downscale factor for quick renders while navigating. 1 disables previews
+    pub frame_budget_ms: Option<u64>, // Time each quick render aims to take, adapting its quality. `None` keeps `preview_scale`
+    pub crossfade_ms: u64, // How long the full quality render fades in over a preview. 0 swaps instantly
+    pub recolour_fade_ms: u64, // How long a change of colouring fades in over the old one. 0 swaps instantly
+    pub heatmap_opacity: f32,  // Starting opacity of the iteration heatmap toggled with `H`
+}
+
+impl CliOptions {
+    /// Parse arguments of the form `--flag value` or `--flag=value`. The program name and any subcommand should
+    /// already be skipped. With a subcommand, flags that only other subcommands take are rejected.
+    pub fn parse(
+        args: impl IntoIterator<Item = String>,
+        subcommand: Option<Subcommand>,
+    ) -> Result<CliOptions, String> {
+        let mut options = CliOptions {
+            fractal: None,
+            view_transform: None,
+            width: None,
+            height: None,
+            max_iterations: None,
+            navigation_iterations: None,
+            importance: None,
+            gamma: None,
+            exterior_lift: None,
+            zoom: None,
+            y_stretch: None,
+            z0: None,
+            julia_c: None,
+            offset_x: None,
+            offset_y: None,
+            supersample: None,
+            downscale_filter: None,
+            sample_pattern: None,
+            sample_seed: None,
+            precision: None,
+            f64_region: None,
+            palette: None,
+            blend_palette: None,
+            palette_blend: None,
+            colour_period: None,
+            colour_offset: None,
+            interior_mode: None,
+            interior_colour: None,
+            degenerate_colour: None,
+            transparency: None,
+            settings: None,
+            session: None,
+            resume: false,
+            output: None,
+            region: None,
+            tiles_dir: None,
+            tile_size: 1024,
+            batch: None,
+            batch_dir: PathBuf::from("."),
+            files: Vec::new(),
+            columns: None,
+            cell: (160, 120),
+            sidecar: false,
+            print_settings: false,
+            sixteen_bit: false,
+            symmetry: Symmetry::None,
+            commands: false,
+            timings: false,
+            snap_clicks: false,
+            no_inertia: false,
+            escape_shape: None,
+            bailout: None,
+            contours: false,
+            edge_blend: false,
+            colour_mode: None,
+            smooth_extra_iterations: None,
+            log_colour: false,
+            preview_scale: 4,
+            frame_budget_ms: None,
+            crossfade_ms: 150,
+            recolour_fade_ms: 250,
+            heatmap_opacity: 0.5,
+            double_click_ms: None,
+            frames_dir: None,
+            frames: None,
+            tour: None,
+            background: [0., 0., 0., 1.],
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if subcommand.is_some_and(Subcommand::takes_files) && !arg.starts_with("--") {
+                options.files.push(PathBuf::from(arg));
+                continue;
+            }
+            // Split `--flag=value` so both forms are handled the same way below
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            if let Some(subcommand) = subcommand {
+                subcommand.check_flag(&flag)?;
+            }
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+
+            match flag.as_str() {
+                "--fractal" => {
+                    let name = value()?;
+                    let kind = FractalKind::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = FractalKind::ALL.iter().map(|k| k.name()).collect();
+                        format!(
+                            "unknown fractal '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.fractal = Some(kind);
+                }
+                "--view" => {
+                    let name = value()?;
+                    let transform = ViewTransform::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> =
+                            ViewTransform::ALL.iter().map(|t| t.name()).collect();
+                        format!(
+                            "unknown view '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.view_transform = Some(transform);
+                }
+                "--interior-mode" => {
+                    let name = value()?;
+                    let mode = InteriorMode::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = InteriorMode::ALL.iter().map(|m| m.name()).collect();
+                        format!(
+                            "unknown interior mode '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.interior_mode = Some(mode);
+                }
+                "--transparency" => {
+                    let name = value()?;
+                    let transparency = Transparency::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = Transparency::ALL.iter().map(|t| t.name()).collect();
+                        format!(
+                            "unknown transparency '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.transparency = Some(transparency);
+                }
+                "--width" => options.width = Some(parse_number(&flag, &value()?)?),
+                "--height" => options.height = Some(parse_number(&flag, &value()?)?),
+                "--max-iterations" => {
+                    let iterations: u32 = parse_number(&flag, &value()?)?;
+                    if iterations == 0 {
+                        return Err("--max-iterations must be at least 1".to_string());
+                    }
+                    options.max_iterations = Some(iterations);
+                }
+                "--navigation-iterations" => {
+                    let iterations: u32 = parse_number(&flag, &value()?)?;
+                    if iterations == 0 {
+                        return Err("--navigation-iterations must be at least 1".to_string());
+                    }
+                    options.navigation_iterations = Some(iterations);
+                }
+                "--importance" => {
+                    let path = PathBuf::from(value()?);
+                    if !path.exists() {
+                        return Err(format!("no importance map at {}", path.display()));
+                    }
+                    options.importance = Some(path);
+                }
+                "--gamma" => {
+                    let gamma: f32 = parse_number(&flag, &value()?)?;
+                    if !(gamma.is_finite() && gamma > 0.) {
+                        return Err("--gamma must be a positive number".to_string());
+                    }
+                    options.gamma = Some(gamma);
+                }
+                "--exterior-lift" => {
+                    let lift: f32 = parse_number(&flag, &value()?)?;
+                    if !(-1. ..=1.).contains(&lift) {
+                        return Err("--exterior-lift must be between -1 and 1".to_string());
+                    }
+                    options.exterior_lift = Some(lift);
+                }
+                "--zoom" => {
+                    let zoom: f32 = parse_number(&flag, &value()?)?;
+                    if !(zoom.is_finite() && zoom > 0.) {
+                        return Err("--zoom must be a positive number".to_string());
+                    }
+                    options.zoom = Some(zoom);
+                }
+                "--z0" => options.z0 = Some(parse_z0(&flag, &value()?)?),
+                "--julia-c" => options.julia_c = Some(parse_complex(&flag, &value()?)?),
+                "--y-stretch" => {
+                    let stretch: f32 = parse_number(&flag, &value()?)?;
+                    if !(stretch.is_finite() && stretch > 0.) {
+                        return Err("--y-stretch must be a positive number".to_string());
+                    }
+                    options.y_stretch = Some(stretch);
+                }
+                "--offset-x" => options.offset_x = Some(parse_number(&flag, &value()?)?),
+                "--offset-y" => options.offset_y = Some(parse_number(&flag, &value()?)?),
+                "--supersample" => {
+                    let factor = parse_number(&flag, &value()?)?;
+                    if factor == 0 {
+                        return Err("--supersample must be at least 1".to_string());
+                    }
+                    options.supersample = Some(factor);
+                }
+                "--downscale-filter" => {
+                    let name = value()?;
+                    let filter = DownscaleFilter::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> =
+                            DownscaleFilter::ALL.iter().map(|f| f.name()).collect();
+                        format!(
+                            "unknown filter '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.downscale_filter = Some(filter);
+                }
+                "--sample-pattern" => {
+                    let name = value()?;
+                    let pattern = SamplePattern::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> =
+                            SamplePattern::ALL.iter().map(|p| p.name()).collect();
+                        format!(
+                            "unknown sample pattern '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.sample_pattern = Some(pattern);
+                }
+                "--sample-seed" => options.sample_seed = Some(parse_number(&flag, &value()?)?),
+                "--precision" => {
+                    let name = value()?;
+                    let precision = Precision::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = Precision::ALL.iter().map(|p| p.name()).collect();
+                        format!(
+                            "unknown precision '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.precision = Some(precision);
+                }
+                "--f64-region" => options.f64_region = Some(parse_region(&flag, &value()?)?),
+                // Not checked here: a palette that can't be loaded falls back to grayscale with a warning
+                "--palette" | "--palette-file" => options.palette = Some(PathBuf::from(value()?)),
+                "--blend-palette" => options.blend_palette = Some(PathBuf::from(value()?)),
+                "--palette-blend" => {
+                    let blend: f32 = parse_number(&flag, &value()?)?;
+                    if !(0. ..=1.).contains(&blend) {
+                        return Err("--palette-blend must be between 0 and 1".to_string());
+                    }
+                    options.palette_blend = Some(blend);
+                }
+                "--colour-period" | "--color-period" => {
+                    let period: f32 = parse_number(&flag, &value()?)?;
+                    if !(period > 0. && period.is_finite()) {
+                        return Err(format!("{} must be a positive number of iterations", flag));
+                    }
+                    options.colour_period = Some(period);
+                }
+                "--colour-offset" | "--color-offset" => {
+                    let offset: f32 = parse_number(&flag, &value()?)?;
+                    if !offset.is_finite() {
+                        return Err(format!("{} must be a finite number", flag));
+                    }
+                    options.colour_offset = Some(offset.rem_euclid(1.)); // Whole turns of the ramp change nothing
+                }
+                "--settings" => options.settings = Some(PathBuf::from(value()?)),
+                "--session" => options.session = Some(PathBuf::from(value()?)),
+                "--resume" => options.resume = true,
+                "--output" => options.output = Some(PathBuf::from(value()?)),
+                "--region" => options.region = Some(parse_region(&flag, &value()?)?),
+                "--sidecar" => options.sidecar = true,
+                "--print-settings" => options.print_settings = true,
+                "--16-bit" => options.sixteen_bit = true,
+                "--symmetry" => {
+                    let name = value()?;
+                    options.symmetry = Symmetry::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = Symmetry::ALL.iter().map(|s| s.name()).collect();
+                        format!(
+                            "unknown symmetry '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                }
+                "--commands" => options.commands = true,
+                "--timings" => options.timings = true,
+                "--snap-clicks" => options.snap_clicks = true,
+                "--no-inertia" => options.no_inertia = true,
+                "--fast-escape" => options.escape_shape = Some(EscapeShape::Diamond),
+                "--escape-shape" => {
+                    let name = value()?;
+                    let shape = EscapeShape::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = EscapeShape::ALL.iter().map(|s| s.name()).collect();
+                        format!(
+                            "unknown escape shape '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.escape_shape = Some(shape);
+                }
+                "--bailout" => {
+                    let bailout: f32 = parse_number(&flag, &value()?)?;
+                    if !(MIN_BAILOUT..=MAX_BAILOUT).contains(&bailout) {
+                        return Err(format!(
+                            "--bailout must be between {} and {}",
+                            MIN_BAILOUT, MAX_BAILOUT
+                        ));
+                    }
+                    options.bailout = Some(bailout);
+                }
+                "--contours" => options.contours = true,
+                "--edge-blend" => options.edge_blend = true,
+                "--log-colour" => options.log_colour = true,
+                "--colour-mode" | "--color-mode" => {
+                    let name = value()?;
+                    let mode = ColourMode::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = ColourMode::ALL.iter().map(|m| m.name()).collect();
+                        format!(
+                            "unknown colour mode '{}', expected one of: {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?;
+                    options.colour_mode = Some(mode);
+                }
+                "--smooth-extra-iterations" => {
+                    let extra = parse_number(&flag, &value()?)?;
+                    if extra > MAX_SMOOTH_EXTRA_ITERATIONS {
+                        return Err(format!(
+                            "--smooth-extra-iterations can be at most {}",
+                            MAX_SMOOTH_EXTRA_ITERATIONS
+                        ));
+                    }
+                    options.smooth_extra_iterations = Some(extra);
+                }
+                "--frames-dir" => options.frames_dir = Some(PathBuf::from(value()?)),
+                "--tour" => options.tour = Some(PathBuf::from(value()?)),
+                "--frames" => {
+                    let frames = parse_number(&flag, &value()?)?;
+                    options.frames = Some(frames);
+                    if frames == 0 {
+                        return Err("--frames must be at least 1".to_string());
+                    }
+                }
+                "--batch" => options.batch = Some(PathBuf::from(value()?)),
+                "--batch-dir" => options.batch_dir = PathBuf::from(value()?),
+                "--columns" => {
+                    let columns = parse_number(&flag, &value()?)?;
+                    if columns == 0 {
+                        return Err("--columns must be at least 1".to_string());
+                    }
+                    options.columns = Some(columns);
+                }
+                "--cell" => {
+                    let value = value()?;
+                    options.cell = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .filter(|&(w, h)| w > 0 && h > 0)
+                        .ok_or_else(|| {
+                            format!("invalid value '{}' for --cell, expected WxH", value)
+                        })?;
+                }
+                "--tiles-dir" => options.tiles_dir = Some(PathBuf::from(value()?)),
+                "--tile-size" => {
+                    options.tile_size = parse_number(&flag, &value()?)?;
+                    if options.tile_size == 0 {
+                        return Err("--tile-size must be at least 1".to_string());
+                    }
+                }
+                "--background" => options.background = parse_colour(&flag, &value()?)?,
+                "--interior-colour" => options.interior_colour = Some(parse_rgb(&flag, &value()?)?),
+                "--degenerate-colour" => {
+                    options.degenerate_colour = Some(parse_rgb(&flag, &value()?)?)
+                }
+                "--double-click-ms" => {
+                    let timeout = parse_number(&flag, &value()?)?;
+                    if !DOUBLE_CLICK_RANGE.contains(&timeout) {
+                        return Err(format!(
+                            "--double-click-ms must be between {} and {}",
+                            DOUBLE_CLICK_RANGE.start(),
+                            DOUBLE_CLICK_RANGE.end()
+                        ));
+                    }
+                    options.double_click_ms = Some(timeout);
+                }
+                "--preview-scale" => {
+                    options.preview_scale = parse_number(&flag, &value()?)?;
+                    if options.preview_scale == 0 {
+                        return Err("--preview-scale must be at least 1".to_string());
+                    }
+                }
+                "--frame-budget-ms" => {
+                    let budget: u64 = parse_number(&flag, &value()?)?;
+                    if budget == 0 {
+                        return Err("--frame-budget-ms must be at least 1".to_string());
+                    }
+                    options.frame_budget_ms = Some(budget);
+                }
+                "--crossfade-ms" => options.crossfade_ms = parse_number(&flag, &value()?)?,
+                "--recolour-fade-ms" => options.recolour_fade_ms = parse_number(&flag, &value()?)?,
+                "--heatmap-opacity" => {
+                    options.heatmap_opacity = parse_number(&flag, &value()?)?;
+                    if !(0. ..=1.).contains(&options.heatmap_opacity) {
+                        return Err("--heatmap-opacity must be between 0 and 1".to_string());
+                    }
+                }
+                _ => return Err(format!("unknown argument '{}'", flag)),
+            }
+        }
+
+        if options.resume && options.settings.is_some() {
+            return Err(
+                "--resume and --settings both choose the starting settings, pick one".to_string(),
+            );
+        }
+        if !options.files.is_empty() && options.settings.is_some() {
+            // Each file is the starting point of its own render
+            return Err("--settings can't go with settings files given as arguments".to_string());
+        }
+        if options.symmetry != Symmetry::None
+            && (options.region.is_some() || options.tiles_dir.is_some())
+        {
+            // The fold lines run through the middle of the whole image, which a part of it doesn't have
+            return Err(
+                "--symmetry folds whole images, so it can't go with --region or --tiles-dir"
+                    .to_string(),
+            );
+        }
+        Ok(options)
+    }
+
+    /// The file the viewer's session is read from and saved to. Only kept when asked for, with `--session` or
+    /// `--resume`, so plain runs don't leave a file behind or overwrite a session kept for later.
+    pub fn session_path(&self) -> Option<PathBuf> {
+        (self.session.clone()).or_else(|| self.resume.then(|| PathBuf::from(DEFAULT_SESSION)))
+    }
+
+    /// Override settings with any values given on the command line.
+    pub fn apply(&self, settings: &mut MandelbrotSettings) {
+        settings.fractal = self.fractal.unwrap_or(settings.fractal);
+        settings.view_transform = self.view_transform.unwrap_or(settings.view_transform);
+        settings.width = self.width.unwrap_or(settings.width);
+        settings.height = self.height.unwrap_or(settings.height);
+        settings.max_iterations = self.max_iterations.unwrap_or(settings.max_iterations);
+        settings.navigation_iterations = self
+            .navigation_iterations
+            .or(settings.navigation_iterations);
+        if let Some(path) = &self.importance {
+            settings.importance = load_importance_or_warn(path);
+        }
+        settings.gamma = self.gamma.unwrap_or(settings.gamma);
+        settings.exterior_lift = self.exterior_lift.unwrap_or(settings.exterior_lift);
+        settings.zoom = self.zoom.unwrap_or(settings.zoom);
+        settings.y_stretch = self.y_stretch.unwrap_or(settings.y_stretch);
+        settings.z0 = self.z0.unwrap_or(settings.z0);
+        settings.julia_c = self.julia_c.unwrap_or(settings.julia_c);
+        settings.offset_x = self.offset_x.unwrap_or(settings.offset_x);
+        settings.offset_y = self.offset_y.unwrap_or(settings.offset_y);
+        settings.escape_shape = self.escape_shape.unwrap_or(settings.escape_shape);
+        settings.bailout = self.bailout.unwrap_or(settings.bailout);
+        settings.contours |= self.contours;
+        settings.edge_blend |= self.edge_blend;
+        settings.log_colour |= self.log_colour;
+        settings.colour_mode = self.colour_mode.unwrap_or(settings.colour_mode);
+        settings.smooth_extra_iterations = self
+            .smooth_extra_iterations
+            .unwrap_or(settings.smooth_extra_iterations);
+        settings.supersample = self.supersample.unwrap_or(settings.supersample);
+        settings.downscale_filter = self.downscale_filter.unwrap_or(settings.downscale_filter);
+        settings.sample_pattern = self.sample_pattern.unwrap_or(settings.sample_pattern);
+        settings.sample_seed = self.sample_seed.unwrap_or(settings.sample_seed);
+        settings.precision = self.precision.unwrap_or(settings.precision);
+        settings.f64_region = self.f64_region.or(settings.f64_region);
+        if let Some(path) = &self.palette {
+            settings.palette = load_palette_or_warn(path); // Loaded once here and shared by every render
+        }
+        if let Some(path) = &self.blend_palette {
+            settings.blend_palette = load_palette_or_warn(path);
+        }
+        settings.palette_blend = self.palette_blend.unwrap_or(settings.palette_blend);
+        settings.colour_period = self.colour_period.or(settings.colour_period);
+        settings.colour_offset = self.colour_offset.unwrap_or(settings.colour_offset);
+        settings.interior_mode = self.interior_mode.unwrap_or(settings.interior_mode);
+        settings.interior_colour = self.interior_colour.or(settings.interior_colour);
+        settings.degenerate_colour = self.degenerate_colour.unwrap_or(settings.degenerate_colour);
+        settings.transparency = self.transparency.unwrap_or(settings.transparency);
+    }
+}
+
+/// Override settings with any `MANDEL_*` environment variables found by `lookup`.
+/// These sit between the defaults and the command line, so a flag always wins over the environment.
+/// Malformed values are reported and ignored rather than stopping the program.
+pub fn apply_env(settings: &mut MandelbrotSettings, lookup: impl Fn(&str) -> Option<String>) {
+    let read = |name: &str| lookup(name).map(|value| (name.to_string(), value));
+    if let Some(value) = read("MANDEL_WIDTH").and_then(parse_env) {
+        settings.width = value;
+    }
+    if let Some(value) = read("MANDEL_HEIGHT").and_then(parse_env) {
+        settings.height = value;
+    }
+    if let Some(value) = read("MANDEL_MAX_ITER").and_then(parse_env) {
+        // No iterations at all would put every point in the set
+        if value > 0 {
+            settings.max_iterations = value;
+        } else {
+            eprintln!("warning: ignoring MANDEL_MAX_ITER=0, it must be at least 1");
+        }
+    }
+    if let Some(value) = read("MANDEL_GAMMA").and_then(parse_env::<f32>) {
+        // A gamma of 0 would flatten the whole exterior to one colour, and a negative one invert the ramp
+        if value.is_finite() && value > 0. {
+            settings.gamma = value;
+        } else {
+            eprintln!(
+                "warning: ignoring MANDEL_GAMMA={}, it must be positive",
+                value
+            );
+        }
+    }
+    if let Some(value) = read("MANDEL_ZOOM").and_then(parse_env::<f32>) {
+        // Zero, negative or infinite zooms would turn every pixel into the same point, or NaN
+        if value.is_finite() && value > 0. {
+            settings.zoom = value;
+        } else {
+            eprintln!(
+                "warning: ignoring MANDEL_ZOOM={}, it must be positive",
+                value
+            );
+        }
+    }
+    if let Some(value) = read("MANDEL_OFFSET_X").and_then(parse_env) {
+        settings.offset_x = value;
+    }
+    if let Some(value) = read("MANDEL_OFFSET_Y").and_then(parse_env) {
+        settings.offset_y = value;
+    }
+}
+
+/// Parse an environment variable's value, warning and returning `None` if it's malformed.
+fn parse_env<T: std::str::FromStr>((name, value): (String, String)) -> Option<T> {
+    let parsed = value.trim().parse().ok();
+    if parsed.is_none() {
+        eprintln!("warning: ignoring malformed {}='{}'", name, value);
+    }
+    parsed
+}
+
+/// Parse a colour written as `#rrggbb` or `r,g,b` with components from 0 to 255, for drawing in the window.
+fn parse_colour(flag: &str, value: &str) -> Result<[f32; 4], String> {
+    let [r, g, b, _] = parse_rgb(flag, value)?;
+    Ok([r as f32 / 255., g as f32 / 255., b as f32 / 255., 1.])
+}
+
+/// Parse a colour like `parse_colour`, as opaque RGBA bytes for rendering.
+fn parse_rgb(flag: &str, value: &str) -> Result<[u8; 4], String> {
+    let invalid = || {
+        format!(
+            "invalid colour '{}' for {}, expected #rrggbb or r,g,b",
+            value, flag
+        )
+    };
+    let [r, g, b]: [u8; 3] = if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 {
+            return Err(invalid());
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(hex.get(i..i + 2).ok_or_else(invalid)?, 16).map_err(|_| invalid())
+        };
+        [channel(0)?, channel(2)?, channel(4)?]
+    } else {
+        let channels = value
+            .split(',')
+            .map(|part| part.trim().parse::<u8>().map_err(|_| invalid()))
+            .collect::<Result<Vec<u8>, String>>()?;
+        channels.try_into().map_err(|_| invalid())?
+    };
+    Ok([r, g, b, 255])
+}
+
+/// Parse a pixel region written as `x0,y0,x1,y1`.
+fn parse_region(flag: &str, value: &str) -> Result<Region, String> {
+    let invalid = || {
+        format!(
+            "invalid region '{}' for {}, expected x0,y0,x1,y1",
+            value, flag
+        )
+    };
+    let corners = value
+        .split(',')
+        .map(|part| part.trim().parse::<u32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<u32>, String>>()?;
+    match corners[..] {
+        [x0, y0, x1, y1] => Ok(Region { x0, y0, x1, y1 }),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse a starting value written as `re,im`, or `c` to start at the point itself.
+fn parse_z0(flag: &str, value: &str) -> Result<Option<Complex<f32>>, String> {
+    if value == "c" {
+        return Ok(None);
+    }
+    parse_complex(flag, value).map(Some).map_err(|_| {
+        format!(
+            "invalid value '{}' for {}, expected re,im or c",
+            value, flag
+        )
+    })
+}
+
+/// Parse a complex number written as `re,im`.
+fn parse_complex(flag: &str, value: &str) -> Result<Complex<f32>, String> {
+    let parts = value
+        .split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>();
+    match parts.as_deref() {
+        Ok([re, im]) if re.is_finite() && im.is_finite() => Ok(Complex::new(*re, *im)),
+        _ => Err(format!(
+            "invalid value '{}' for {}, expected re,im",
+            value, flag
+        )),
+    }
+}
+
+/// Parse a flag's value, naming the flag in the error.
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Defaults, then `env` as the environment, then `args` on the command line, as `main` layers them.
+    fn layered(env: &[(&str, &str)], args: &[&str]) -> MandelbrotSettings {
+        let mut settings = MandelbrotSettings::default();
+        apply_env(&mut settings, |name| {
+            env.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        });
+        let options = CliOptions::parse(args.iter().map(|arg| arg.to_string()), None).unwrap();
+        options.apply(&mut settings);
+        settings
+    }
+
+    #[test]
+    fn environment_overrides_defaults_and_flags_override_environment() {
+        let defaults = MandelbrotSettings::default();
+        let env = [("MANDEL_MAX_ITER", "500"), ("MANDEL_GAMMA", "0.5")];
+
+        let settings = layered(&[], &[]);
+        assert_eq!(settings.max_iterations, defaults.max_iterations);
+
+        let settings = layered(&env, &[]);
+        assert_eq!(settings.max_iterations, 500);
+        assert_eq!(settings.gamma, 0.5);
+
+        let settings = layered(&env, &["--max-iterations", "700"]);
+        assert_eq!(settings.max_iterations, 700);
+        assert_eq!(settings.gamma, 0.5); // Not on the command line, so the environment's still stands
+    }
+
+    #[test]
+    fn malformed_environment_values_fall_back() {
+        let settings = layered(&[("MANDEL_MAX_ITER", "lots"), ("MANDEL_GAMMA", "0.4")], &[]);
+        assert_eq!(
+            settings.max_iterations,
+            MandelbrotSettings::default().max_iterations
+        );
+        assert_eq!(settings.gamma, 0.4);
+    }
+
+    #[test]
+    fn zoom_must_be_positive_and_finite() {
+        for zoom in ["0", "-2", "inf", "NaN"] {
+            assert!(CliOptions::parse(["--zoom", zoom].map(String::from), None).is_err());
+            let settings = layered(&[("MANDEL_ZOOM", zoom)], &[]);
+            assert_eq!(settings.zoom, MandelbrotSettings::default().zoom);
+        }
+        assert_eq!(layered(&[("MANDEL_ZOOM", "8")], &["--zoom", "3"]).zoom, 3.);
+    }
+
+    #[test]
+    fn gamma_must_be_positive_and_finite() {
+        for gamma in ["0", "-0.5", "inf", "NaN"] {
+            assert!(CliOptions::parse(["--gamma", gamma].map(String::from), None).is_err());
+            let settings = layered(&[("MANDEL_GAMMA", gamma)], &[]);
+            assert_eq!(settings.gamma, MandelbrotSettings::default().gamma);
+        }
+    }
+
+    #[test]
+    fn unknown_palettes_are_left_to_fall_back() {
+        let options = CliOptions::parse(["--palette", "no-such-palette"].map(String::from), None);
+        assert_eq!(
+            options.unwrap().palette,
+            Some(PathBuf::from("no-such-palette"))
+        );
+    }
+
+    #[test]
+    fn max_iterations_must_be_at_least_1() {
+        assert!(CliOptions::parse(["--max-iterations", "0"].map(String::from), None).is_err());
+        let settings = layered(&[("MANDEL_MAX_ITER", "0")], &[]);
+        assert_eq!(
+            settings.max_iterations,
+            MandelbrotSettings::default().max_iterations
+        );
+    }
+
+    #[test]
+    fn diff_and_montage_take_files_and_only_their_own_flags() {
+        let parse =
+            |sub, args: &[&str]| CliOptions::parse(args.iter().map(|a| a.to_string()), Some(sub));
+        let options = parse(
+            Subcommand::Montage,
+            &[
+                "a.json",
+                "--cell",
+                "80x60",
+                "b.json",
+                "--columns=3",
+                "--zoom",
+                "4",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            options.files,
+            [PathBuf::from("a.json"), PathBuf::from("b.json")]
+        );
+        assert_eq!(
+            (options.cell, options.columns, options.zoom),
+            ((80, 60), Some(3), Some(4.))
+        );
+
+        assert!(parse(Subcommand::Diff, &["a.json", "b.json", "--output", "d.png"]).is_ok());
+        assert!(parse(Subcommand::Diff, &["a.json", "b.json", "--columns", "2"]).is_err());
+        assert!(parse(Subcommand::Diff, &["a.json", "--settings", "b.json"]).is_err());
+        assert!(parse(Subcommand::Montage, &["a.json", "--cell", "80"]).is_err());
+        assert!(parse(Subcommand::Render, &["--columns", "2"]).is_err());
+        assert!(parse(Subcommand::Render, &["a.json"]).is_err());
+    }
+}
@@ -34,6 +34,12 @@ impl DoubleClickHandler {
         false
     }
 
+    /// Don't let the last click count as the first of a double click, e.g. after it turned into a drag.
+    pub fn forget_click(&mut self) {
+        self.last_click =
+            (Instant::now().checked_sub(self.click_timeout)).unwrap_or(self.last_click);
+    }
+
     /// Check if the time between the last click and now is less than the click timeout
     fn is_double_click(&mut self) -> bool {
         let now = Instant::now();
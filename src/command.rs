@@ -0,0 +1,116 @@
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+
+/// A line of the stdin command mode, enabled with `--commands`.
+pub enum Command {
+    Zoom(f32),          // `zoom Z`: set the zoom, keeping the centre
+    Center(f32, f32),   // `center X Y`: move the centre, keeping the zoom
+    Iterations(u32),    // `iter N`: set the maximum iterations
+    Save(PathBuf),      // `save FILE`: render the current settings at full quality to a file
+    Render,             // `render`: show the changes made so far in the window
+    Waypoints,          // `waypoints`: list the tour's waypoints
+    Remove(usize),      // `remove N`: take waypoint N, counting from 1, out of the tour
+    Move(usize, usize), // `move N M`: move waypoint N to position M in the tour
+    Area(usize),        // `area [N]`: estimate the set's area in view from N random points
+}
+
+/// Random points `area` iterates when not given a number.
+pub const DEFAULT_AREA_SAMPLES: usize = 1_000_000;
+
+/// Names of every command, for error messages.
+const COMMAND_NAMES: &str = "zoom, center, iter, save, render, waypoints, remove, move, area";
+
+impl Command {
+    /// Parse a command line such as `center -0.5 0`.
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("empty command")?;
+        let arguments: Vec<&str> = words.collect();
+
+        // Every numeric command takes a fixed number of arguments
+        let numbers = |count: usize| -> Result<Vec<f32>, String> {
+            if arguments.len() != count {
+                return Err(format!(
+                    "'{}' takes {} argument{}, got {}",
+                    name,
+                    count,
+                    if count == 1 { "" } else { "s" },
+                    arguments.len()
+                ));
+            }
+            arguments
+                .iter()
+                .map(|word| {
+                    word.parse()
+                        .map_err(|_| format!("'{}' isn't a number", word))
+                })
+                .collect()
+        };
+
+        match name {
+            "zoom" => Ok(Command::Zoom(numbers(1)?[0])),
+            "center" | "centre" => {
+                let xy = numbers(2)?;
+                Ok(Command::Center(xy[0], xy[1]))
+            }
+            "iter" => match arguments[..] {
+                [word] => match word.parse() {
+                    Ok(iterations) if iterations >= 1 => Ok(Command::Iterations(iterations)),
+                    _ => Err(format!("'{}' isn't a whole number of at least 1", word)),
+                },
+                _ => Err("'iter' takes 1 argument".to_string()),
+            },
+            "save" => match arguments[..] {
+                [] => Err("'save' needs a file name".to_string()),
+                _ => Ok(Command::Save(PathBuf::from(arguments.join(" ")))), // Allow spaces in the path
+            },
+            "render" if arguments.is_empty() => Ok(Command::Render),
+            "render" => Err("'render' takes no arguments".to_string()),
+            "waypoints" if arguments.is_empty() => Ok(Command::Waypoints),
+            "waypoints" => Err("'waypoints' takes no arguments".to_string()),
+            "remove" | "move" => {
+                // Waypoint positions, counting from 1 as `waypoints` lists them
+                let positions = arguments
+                    .iter()
+                    .map(|word| match word.parse::<usize>() {
+                        Ok(position) if position >= 1 => Ok(position - 1),
+                        _ => Err(format!("'{}' isn't a waypoint number", word)),
+                    })
+                    .collect::<Result<Vec<usize>, String>>()?;
+                match (name, &positions[..]) {
+                    ("remove", [index]) => Ok(Command::Remove(*index)),
+                    ("move", [from, to]) => Ok(Command::Move(*from, *to)),
+                    ("remove", _) => Err("'remove' takes 1 argument".to_string()),
+                    _ => Err("'move' takes 2 arguments".to_string()),
+                }
+            }
+            "area" => match arguments[..] {
+                [] => Ok(Command::Area(DEFAULT_AREA_SAMPLES)),
+                [word] => match word.parse() {
+                    Ok(samples) if samples >= 1 => Ok(Command::Area(samples)),
+                    _ => Err(format!("'{}' isn't a whole number of at least 1", word)),
+                },
+                _ => Err("'area' takes at most 1 argument".to_string()),
+            },
+            _ => Err(format!(
+                "unknown command '{}', expected one of: {}",
+                name, COMMAND_NAMES
+            )),
+        }
+    }
+}
+
+/// Read stdin on a background thread, so the event loop can pick up lines without blocking.
+/// Blank lines are skipped. The thread ends when stdin closes.
+pub fn read_stdin_lines() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if !line.trim().is_empty() && sender.send(line).is_err() {
+                break; // The window has closed
+            }
+        }
+    });
+    receiver
+}
@@ -0,0 +1,108 @@
+use crate::mandelbrot_settings::MandelbrotSettings;
+use num_complex::Complex;
+
+/// A callback told about changes, with the new settings.
+type Observer = Box<dyn FnMut(&MandelbrotSettings)>;
+
+/// Identifies a callback registered with `ViewController::on_change`, for removing it again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+/// Owns the settings of a view and tells registered callbacks whenever they change, so a host app can keep its
+/// UI, a URL bar or a navigation log in step without checking after every call. Changes go through the
+/// navigation methods here, or `update` for anything else, and a callback fires once per call that changed
+/// something, with the new settings.
+///
+/// A change is anything that alters the settings' JSON form, so a zoom clamped at the precision limit or a jump
+/// to the current view notifies nobody. With no callbacks registered nothing is compared, and the controller is
+/// just the settings.
+///
+/// The viewer doesn't go through this. It has to tell apart changes that need a render, a recolour of the counts
+/// showing, a strip render after a pan or just a new title, which one notification per change can't, so it keeps
+/// its own flags for those.
+pub struct ViewController {
+    settings: MandelbrotSettings,
+    observers: Vec<(ObserverId, Observer)>, // Called in the order they were added
+    next_id: u64,
+}
+
+impl ViewController {
+    pub fn new(settings: MandelbrotSettings) -> ViewController {
+        ViewController {
+            settings,
+            observers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// The current settings, e.g. to render them.
+    pub fn settings(&self) -> &MandelbrotSettings {
+        &self.settings
+    }
+
+    /// Give up the controller for its settings, dropping the callbacks.
+    pub fn into_settings(self) -> MandelbrotSettings {
+        self.settings
+    }
+
+    /// Call `callback` with the new settings after every change from now on.
+    pub fn on_change(&mut self, callback: impl FnMut(&MandelbrotSettings) + 'static) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push((id, Box::new(callback)));
+        id
+    }
+
+    /// Stop calling a callback added with `on_change`. Returns whether it was still registered.
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        let before = self.observers.len();
+        self.observers.retain(|(observer, _)| *observer != id);
+        self.observers.len() < before
+    }
+
+    /// Change the settings with `change`, then tell the callbacks if anything changed. Returns what `change`
+    /// returned, such as whether a zoom was applied.
+    pub fn update<R>(&mut self, change: impl FnOnce(&mut MandelbrotSettings) -> R) -> R {
+        if self.observers.is_empty() {
+            return change(&mut self.settings);
+        }
+        let before = self.settings.to_json();
+        let result = change(&mut self.settings);
+        if self.settings.to_json() != before {
+            for (_, callback) in &mut self.observers {
+                callback(&self.settings);
+            }
+        }
+        result
+    }
+
+    /// `MandelbrotSettings::zoom_by`, telling the callbacks if the zoom changed.
+    pub fn zoom_by(&mut self, factor: f32) -> bool {
+        self.update(|settings| settings.zoom_by(factor))
+    }
+
+    /// `MandelbrotSettings::zoom_about`, telling the callbacks if the view changed.
+    pub fn zoom_about(&mut self, target: Complex<f32>, pixel: [f32; 2], factor: f32) -> bool {
+        self.update(|settings| settings.zoom_about(target, pixel, factor))
+    }
+
+    /// `MandelbrotSettings::pan_pixels`, telling the callbacks about the new offset.
+    pub fn pan_pixels(&mut self, dx: i32, dy: i32) {
+        self.update(|settings| settings.pan_pixels(dx, dy))
+    }
+
+    /// `MandelbrotSettings::set_view`, telling the callbacks if the view changed.
+    pub fn set_view(&mut self, x: f32, y: f32, zoom: f32) -> Result<(), String> {
+        self.update(|settings| settings.set_view(x, y, zoom))
+    }
+
+    /// `MandelbrotSettings::go_to`, telling the callbacks if the view changed.
+    pub fn go_to(&mut self, text: &str) -> Result<(), String> {
+        self.update(|settings| settings.go_to(text))
+    }
+
+    /// Set the iteration limit, at least 1, telling the callbacks if it changed.
+    pub fn set_max_iterations(&mut self, max_iterations: u32) {
+        self.update(|settings| settings.max_iterations = max_iterations.max(1))
+    }
+}
@@ -0,0 +1,78 @@
+use crate::mandelbrot_settings::MandelbrotSettings;
+use crate::parallel::*;
+use crate::render::render_counts;
+use image::{ImageBuffer, Rgba};
+
+/// Per-pixel comparison of the iteration counts of two renders, for checking that an optimisation leaves
+/// the output alone. Counts are compared at the supersampled resolution, before colouring.
+pub struct CountDiff {
+    pub deltas: Vec<f32>, // Absolute difference per sample. Infinite where only one render is degenerate
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CountDiff {
+    /// Render both settings and compare them. They must iterate the same number of samples in each direction.
+    pub fn new(a: &MandelbrotSettings, b: &MandelbrotSettings) -> Result<CountDiff, String> {
+        let (a_counts, width, height) = render_counts(a);
+        let (b_counts, b_width, b_height) = render_counts(b);
+        if (width, height) != (b_width, b_height) {
+            return Err(format!(
+                "can't compare a {}x{} render with a {}x{} one",
+                width, height, b_width, b_height
+            ));
+        }
+        let deltas = a_counts
+            .par_iter()
+            .zip(b_counts.par_iter())
+            .map(|(&a, &b)| match (a.is_finite(), b.is_finite()) {
+                (true, true) => (a - b).abs(),
+                (false, false) => 0., // Degenerate in both, which is as alike as they can be
+                _ => f32::INFINITY,
+            })
+            .collect();
+        Ok(CountDiff {
+            deltas,
+            width,
+            height,
+        })
+    }
+
+    /// Largest difference, infinite if any sample is degenerate in only one render.
+    pub fn max(&self) -> f32 {
+        self.deltas.iter().copied().fold(0., f32::max)
+    }
+
+    /// Mean difference over every sample.
+    pub fn mean(&self) -> f32 {
+        self.deltas.iter().sum::<f32>() / self.deltas.len().max(1) as f32
+    }
+
+    /// How many samples differ at all.
+    pub fn differing(&self) -> usize {
+        self.deltas.iter().filter(|&&delta| delta > 0.).count()
+    }
+
+    /// Black where the renders agree and red where they don't, brighter for larger differences relative to
+    /// the largest finite one. Even the smallest difference is clearly visible. Magenta marks samples that are
+    /// degenerate in only one render.
+    pub fn to_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let largest = self
+            .deltas
+            .iter()
+            .copied()
+            .filter(|delta| delta.is_finite())
+            .fold(0., f32::max);
+        let mut img = ImageBuffer::new(self.width, self.height);
+        for (pixel, &delta) in img.pixels_mut().zip(&self.deltas) {
+            *pixel = if delta == 0. {
+                Rgba([0, 0, 0, 255])
+            } else if delta.is_finite() {
+                Rgba([(64. + 191. * delta / largest) as u8, 0, 0, 255])
+            } else {
+                Rgba([255, 0, 255, 255])
+            };
+        }
+        img
+    }
+}
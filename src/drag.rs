@@ -0,0 +1,174 @@
+use std::time::{Duration, Instant};
+
+/// Distance in pixels the cursor has to move with the button down before it counts as a drag, so the small
+/// wobble of a click doesn't move the view.
+const DRAG_THRESHOLD: f64 = 4.;
+/// Only the cursor's movement over this long before the release sets the glide's speed, so slowing down
+/// before letting go gives a gentle glide and stopping gives none.
+const VELOCITY_WINDOW: Duration = Duration::from_millis(80);
+/// Time for the glide's speed to fall to 1/e of what it was.
+const GLIDE_DECAY_SECONDS: f64 = 0.3;
+/// The glide stops once its speed, in pixels per second, falls below this, rather than creeping on forever.
+const GLIDE_STOP_SPEED: f64 = 20.;
+
+/// Pans the view by dragging it, and with inertia lets it glide on after the button is released with the speed
+/// the cursor had, slowing to a stop. Feed it the button presses and releases and the cursor's moves, and ask
+/// `glide` each update how far the glide has carried the view. All positions and moves are in image pixels.
+pub struct DragPan {
+    pub inertia: bool, // Glide after a release. Without it the view stops dead where it was let go
+    grab: Option<Grab>,
+    velocity: [f64; 2], // Of the glide, in pixels per second. Zero when not gliding
+}
+
+/// The state of a drag while the button is down.
+struct Grab {
+    start: [f64; 2],
+    last: [f64; 2],
+    dragging: bool, // Moved past `DRAG_THRESHOLD`, so it's a drag and not a click
+    recent: Vec<(Instant, [f64; 2])>, // Positions within `VELOCITY_WINDOW` of the latest, oldest first
+}
+
+impl DragPan {
+    pub fn new(inertia: bool) -> DragPan {
+        DragPan {
+            inertia,
+            grab: None,
+            velocity: [0., 0.],
+        }
+    }
+
+    /// The button went down at `position`. Grabbing the view also stops any glide.
+    pub fn press(&mut self, position: [f64; 2], now: Instant) {
+        self.velocity = [0., 0.];
+        self.grab = Some(Grab {
+            start: position,
+            last: position,
+            dragging: false,
+            recent: vec![(now, position)],
+        });
+    }
+
+    /// The cursor moved to `position`. Returns how far to drag the view's content, once the cursor is far
+    /// enough from where the button went down for this to be a drag.
+    pub fn move_to(&mut self, position: [f64; 2], now: Instant) -> Option<[f64; 2]> {
+        let grab = self.grab.as_mut()?;
+        let moved = [position[0] - grab.last[0], position[1] - grab.last[1]];
+        grab.recent.push((now, position));
+        grab.recent
+            .retain(|(time, _)| now.duration_since(*time) <= VELOCITY_WINDOW);
+        let from_start = (position[0] - grab.start[0]).hypot(position[1] - grab.start[1]);
+        if !grab.dragging && from_start < DRAG_THRESHOLD {
+            return None;
+        }
+        // The first move past the threshold catches up on everything since the press
+        let moved = match grab.dragging {
+            true => moved,
+            false => [position[0] - grab.start[0], position[1] - grab.start[1]],
+        };
+        grab.dragging = true;
+        grab.last = position;
+        Some(moved)
+    }
+
+    /// The button came up. Returns whether this ended a drag, rather than a click. With inertia, a drag that
+    /// was still moving starts the glide.
+    pub fn release(&mut self, now: Instant) -> bool {
+        let Some(grab) = self.grab.take() else {
+            return false;
+        };
+        if !grab.dragging || !self.inertia {
+            return grab.dragging;
+        }
+        // Average over the recent positions. If the cursor had come to rest they've all aged out but the last
+        let recent: Vec<_> = (grab.recent.into_iter())
+            .filter(|(time, _)| now.duration_since(*time) <= VELOCITY_WINDOW)
+            .collect();
+        if let [(first_time, first), .., (last_time, last)] = recent[..] {
+            let seconds = last_time.duration_since(first_time).as_secs_f64();
+            if seconds > 0. {
+                self.velocity = [
+                    (last[0] - first[0]) / seconds,
+                    (last[1] - first[1]) / seconds,
+                ];
+            }
+        }
+        true
+    }
+
+    /// Whether the view is still gliding after a release.
+    pub fn is_gliding(&self) -> bool {
+        self.velocity != [0., 0.]
+    }
+
+    /// How far the glide carries the view's content over the `dt` seconds since the last update, slowing it
+    /// down as it goes. `None` once it has stopped.
+    pub fn glide(&mut self, dt: f64) -> Option<[f64; 2]> {
+        if !self.is_gliding() {
+            return None;
+        }
+        // Move by the integral of the decaying speed over the step, so the total distance doesn't depend on
+        // the frame rate
+        let decay = (-dt / GLIDE_DECAY_SECONDS).exp();
+        let distance = GLIDE_DECAY_SECONDS * (1. - decay);
+        let moved = self.velocity.map(|v| v * distance);
+        self.velocity = self.velocity.map(|v| v * decay);
+        if self.velocity[0].hypot(self.velocity[1]) < GLIDE_STOP_SPEED {
+            self.velocity = [0., 0.];
+        }
+        Some(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drag from (0, 0) to the right at `speed` pixels per second for 100 ms, in 10 ms steps, and let go
+    /// `pause` after the last move.
+    fn flick(drag: &mut DragPan, speed: f64, pause: Duration) -> bool {
+        let start = Instant::now();
+        drag.press([0., 0.], start);
+        for step in 1..=10 {
+            let time = start + Duration::from_millis(10 * step);
+            drag.move_to([speed * 0.01 * step as f64, 0.], time);
+        }
+        drag.release(start + Duration::from_millis(100) + pause)
+    }
+
+    #[test]
+    fn a_flick_glides_to_a_stop() {
+        let mut drag = DragPan::new(true);
+        assert!(flick(&mut drag, 1000., Duration::ZERO));
+        let mut travelled = 0.;
+        let mut frames = 0;
+        while let Some([dx, dy]) = drag.glide(1. / 60.) {
+            assert!(dx > 0. && dy == 0.);
+            travelled += dx;
+            frames += 1;
+            assert!(frames < 600, "the glide never stopped");
+        }
+        // Most of speed * decay time, less what's lost to stopping early
+        assert!((250. ..300.).contains(&travelled), "glided {}", travelled);
+    }
+
+    #[test]
+    fn no_glide_without_inertia_or_after_stopping() {
+        let mut drag = DragPan::new(false);
+        assert!(flick(&mut drag, 1000., Duration::ZERO));
+        assert!(!drag.is_gliding());
+
+        let mut drag = DragPan::new(true);
+        assert!(flick(&mut drag, 1000., Duration::from_millis(200)));
+        assert!(!drag.is_gliding());
+    }
+
+    #[test]
+    fn small_moves_are_clicks() {
+        let mut drag = DragPan::new(true);
+        let now = Instant::now();
+        drag.press([10., 10.], now);
+        assert_eq!(drag.move_to([12., 11.], now), None);
+        assert!(!drag.release(now));
+        assert!(!drag.is_gliding());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::json::Value;
+use crate::mandelbrot_settings::{MandelbrotSettings, WORLD_SPAN};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// One view a fly-through passes through: a centre and a zoom. The colouring and everything else stay as they
+/// are in the settings the tour is played with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Waypoint {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub zoom: f32,
+}
+
+impl Waypoint {
+    /// The view `settings` is currently showing.
+    pub fn of(settings: &MandelbrotSettings) -> Waypoint {
+        Waypoint {
+            offset_x: settings.offset_x,
+            offset_y: settings.offset_y,
+            zoom: settings.zoom,
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert("offset_x".to_string(), Value::from_f32(self.offset_x));
+        map.insert("offset_y".to_string(), Value::from_f32(self.offset_y));
+        map.insert("zoom".to_string(), Value::from_f32(self.zoom));
+        Value::Object(map)
+    }
+
+    /// Read a view written by `to_json`. The error says what's wrong with it, to follow where it was.
+    pub fn from_json(json: &Value) -> Result<Waypoint, String> {
+        let number = |key: &str| {
+            json.get(key)
+                .and_then(Value::as_f64)
+                .map(|n| n as f32)
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("needs a number '{}'", key))
+        };
+        let zoom = number("zoom")?;
+        if zoom <= 0. {
+            return Err("has a zoom that isn't positive".to_string());
+        }
+        Ok(Waypoint {
+            offset_x: number("offset_x")?,
+            offset_y: number("offset_y")?,
+            zoom,
+        })
+    }
+
+    /// The zoom as a logarithm, which the path is interpolated in so each doubling takes the same time however
+    /// deep it is.
+    fn log_zoom(self) -> f64 {
+        (self.zoom as f64).ln()
+    }
+
+    /// How far the eye travels from here to `next`: the zoom change in e-folds, and the pan in view widths at
+    /// whichever end is further out, since that's where the motion is seen. Used to give each stretch of the
+    /// path time in proportion, so the tour moves at an even pace.
+    fn distance_to(self, next: Waypoint) -> f64 {
+        let zoom = (next.zoom as f64 / self.zoom as f64).ln();
+        let pan = (next.offset_x as f64 - self.offset_x as f64)
+            .hypot(next.offset_y as f64 - self.offset_y as f64)
+            * self.zoom.min(next.zoom) as f64
+            / WORLD_SPAN as f64;
+        zoom.hypot(pan)
+    }
+}
+
+/// An ordered list of waypoints for a guided tour. The log of the zoom follows a Catmull-Rom spline through
+/// them, so its rate changes smoothly from one stretch to the next, and the centre moves so each stretch looks like
+/// a single zoom about one fixed point rather than a pan and a zoom fighting each other. Saved as
+/// `{"waypoints": [{"offset_x": ..., "offset_y": ..., "zoom": ...}, ...]}`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlyPath {
+    pub waypoints: Vec<Waypoint>,
+}
+
+impl FlyPath {
+    /// Length of the whole tour, in the units of `Waypoint::distance_to`.
+    pub fn length(&self) -> f64 {
+        let points = &self.waypoints;
+        points.windows(2).map(|w| w[0].distance_to(w[1])).sum()
+    }
+
+    /// Take waypoint `from` out and put it back at position `to`, moving the ones in between along.
+    /// Positions count from 0.
+    pub fn move_waypoint(&mut self, from: usize, to: usize) -> Result<(), String> {
+        let count = self.waypoints.len();
+        if from >= count || to >= count {
+            return Err(format!("there are only {} waypoints", count));
+        }
+        let waypoint = self.waypoints.remove(from);
+        self.waypoints.insert(to, waypoint);
+        Ok(())
+    }
+
+    /// Remove waypoint `index`, counting from 0.
+    pub fn remove(&mut self, index: usize) -> Result<Waypoint, String> {
+        if index >= self.waypoints.len() {
+            return Err(format!("there are only {} waypoints", self.waypoints.len()));
+        }
+        Ok(self.waypoints.remove(index))
+    }
+
+    /// The view `t` of the way through the tour, from 0 at the first waypoint to 1 at the last, with the rest of
+    /// the settings taken from `settings`. Every stretch between waypoints gets time in proportion to its
+    /// length (see `Waypoint::distance_to`), and within it the spline passes smoothly on to the next stretch.
+    /// Fails if there are no waypoints, or the spline swings past the precision limit.
+    pub fn view_at(
+        &self,
+        settings: &MandelbrotSettings,
+        t: f32,
+    ) -> Result<MandelbrotSettings, String> {
+        let points = &self.waypoints;
+        let [x, y, log_zoom] = match points.len() {
+            0 => return Err("the path has no waypoints".to_string()),
+            1 => [
+                points[0].offset_x as f64,
+                points[0].offset_y as f64,
+                points[0].log_zoom(),
+            ],
+            count => {
+                // Find the stretch `t` falls in by the share of the whole length before it. Repeated
+                // waypoints have no length, so a path of nothing but those is split evenly instead
+                let lengths: Vec<f64> = points.windows(2).map(|w| w[0].distance_to(w[1])).collect();
+                let total: f64 = lengths.iter().sum();
+                let lengths = if total > 0. {
+                    lengths
+                } else {
+                    vec![1.; count - 1]
+                };
+                let mut along = t.clamp(0., 1.) as f64 * lengths.iter().sum::<f64>();
+                let mut segment = 0;
+                while segment < count - 2 && along > lengths[segment] {
+                    along -= lengths[segment];
+                    segment += 1;
+                }
+                let u = match lengths[segment] {
+                    0. => 0.,
+                    length => (along / length).clamp(0., 1.),
+                };
+
+                // The zoom's spline through a stretch also looks at the waypoints either side, repeating the ends
+                let at = |i: isize| points[i.clamp(0, count as isize - 1) as usize].log_zoom();
+                let i = segment as isize;
+                let log_zoom = catmull_rom([at(i - 1), at(i), at(i + 1), at(i + 2)], u);
+
+                // A zoom from z1 to z2 that moves the centre from c1 to c2 keeps one point still, and with the
+                // zoom growing steadily the centre has then gone (1 - z1/z) / (1 - z1/z2) of the way. A stretch
+                // that barely zooms is just a pan, which goes at an even speed
+                let (from, to) = (points[segment], points[segment + 1]);
+                let ratio = from.log_zoom() - to.log_zoom();
+                let share = if ratio.abs() > 1e-6 {
+                    (1. - (ratio * u).exp()) / (1. - ratio.exp())
+                } else {
+                    u
+                };
+                let lerp = |a: f32, b: f32| a as f64 + (b as f64 - a as f64) * share;
+                [
+                    lerp(from.offset_x, to.offset_x),
+                    lerp(from.offset_y, to.offset_y),
+                    log_zoom,
+                ]
+            }
+        };
+        let mut view = settings.clone();
+        view.set_view(x as f32, y as f32, log_zoom.exp() as f32)?;
+        Ok(view)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let waypoints = self
+            .waypoints
+            .iter()
+            .map(|waypoint| waypoint.to_json())
+            .collect();
+        let mut map = BTreeMap::new();
+        map.insert("waypoints".to_string(), Value::Array(waypoints));
+        Value::Object(map)
+    }
+
+    pub fn from_json(json: &Value) -> Result<FlyPath, String> {
+        let list = json
+            .get("waypoints")
+            .and_then(Value::as_array)
+            .ok_or("missing 'waypoints' list")?;
+        let waypoints = list
+            .iter()
+            .enumerate()
+            .map(|(index, waypoint)| {
+                Waypoint::from_json(waypoint)
+                    .map_err(|message| format!("waypoint {} {}", index + 1, message))
+            })
+            .collect::<Result<Vec<Waypoint>, String>>()?;
+        Ok(FlyPath { waypoints })
+    }
+
+    pub fn load(path: &Path) -> Result<FlyPath, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let json = Value::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        FlyPath::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json().to_pretty_string())
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// The value `u` of the way from `p1` to `p2` on the uniform Catmull-Rom spline through all four, which passes
+/// through every waypoint with the slope at each set by its neighbours, so there are no corners.
+fn catmull_rom([p0, p1, p2, p3]: [f64; 4], u: f64) -> f64 {
+    0.5 * (2. * p1
+        + (p2 - p0) * u
+        + (2. * p0 - 5. * p1 + 4. * p2 - p3) * u * u
+        + (3. * (p1 - p2) + p3 - p0) * u * u * u)
+}
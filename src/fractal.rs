@@ -0,0 +1,41 @@
+use num_complex::Complex;
+use num_traits::Num;
+use std::ops::Neg;
+
+/// Which escape-time fractal to iterate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FractalKind {
+    Mandelbrot, // z = z^2 + c
+    Tricorn,    // z = conj(z)^2 + c, the "Mandelbar" with three-fold symmetry
+    Julia,      // z = z^2 + julia_c, starting from the point itself rather than iterating over c
+}
+
+impl FractalKind {
+    pub const ALL: [FractalKind; 3] = [
+        FractalKind::Mandelbrot,
+        FractalKind::Tricorn,
+        FractalKind::Julia,
+    ];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            FractalKind::Mandelbrot => "mandelbrot",
+            FractalKind::Tricorn => "tricorn",
+            FractalKind::Julia => "julia",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FractalKind> {
+        FractalKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+}
+
+/// One Tricorn step. Conjugating just negates the imaginary part, so this costs the same as the Mandelbrot step.
+#[inline(always)]
+pub fn tricorn_step<T: Copy + Num + Neg<Output = T>>(z: Complex<T>, c: Complex<T>) -> Complex<T> {
+    let z = z.conj();
+    z * z + c
+}
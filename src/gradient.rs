@@ -0,0 +1,179 @@
+use crate::json::Value;
+use crate::mandelbrot_settings::colour_from_json;
+use image::Rgba;
+use std::borrow::Cow;
+
+/// A colour ramp given by stops: colours at positions along it, with the colours in between blended linearly
+/// from one stop to the next. Before the first stop and after the last, the end colours carry on.
+/// Two stops at the same position make a hard edge, jumping from one colour to the other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    stops: Cow<'static, [(f32, Rgba<u8>)]>, // Sorted by position. Borrowed for the constants below
+}
+
+/// An opaque colour, to keep the constants short.
+const fn rgb(r: u8, g: u8, b: u8) -> Rgba<u8> {
+    Rgba([r, g, b, 255])
+}
+
+impl Gradient {
+    /// Black through red and orange to pale yellow.
+    pub const FIRE: Gradient = Gradient {
+        stops: Cow::Borrowed(&[
+            (0., rgb(0, 0, 0)),
+            (0.25, rgb(128, 0, 0)),
+            (0.5, rgb(230, 60, 0)),
+            (0.75, rgb(255, 170, 0)),
+            (1., rgb(255, 255, 160)),
+        ]),
+    };
+
+    /// Black through deep and light blue to white.
+    pub const ICE: Gradient = Gradient {
+        stops: Cow::Borrowed(&[
+            (0., rgb(0, 0, 0)),
+            (0.25, rgb(10, 20, 80)),
+            (0.5, rgb(30, 100, 200)),
+            (0.75, rgb(120, 210, 255)),
+            (1., rgb(255, 255, 255)),
+        ]),
+    };
+
+    /// Violet round the spectrum to red, evenly spaced.
+    pub const RAINBOW: Gradient = Gradient {
+        stops: Cow::Borrowed(&[
+            (0., rgb(60, 0, 100)),
+            (1. / 6., rgb(0, 0, 255)),
+            (2. / 6., rgb(0, 200, 255)),
+            (3. / 6., rgb(0, 220, 0)),
+            (4. / 6., rgb(255, 230, 0)),
+            (5. / 6., rgb(255, 100, 0)),
+            (1., rgb(200, 0, 0)),
+        ]),
+    };
+
+    /// A gradient through `stops`, in any order. Stops at the same position keep the order they're given in.
+    /// Fails if there are none, or a position isn't a finite number.
+    pub fn new(mut stops: Vec<(f32, Rgba<u8>)>) -> Result<Gradient, String> {
+        if stops.is_empty() {
+            return Err("a gradient needs at least one stop".to_string());
+        }
+        if let Some((position, _)) = stops.iter().find(|(position, _)| !position.is_finite()) {
+            return Err(format!(
+                "gradient stop at {} isn't a finite position",
+                position
+            ));
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0)); // Stable, so equal positions stay in order
+        Ok(Gradient {
+            stops: Cow::Owned(stops),
+        })
+    }
+
+    /// `colours` spread evenly from 0 to 1, as in a gradient strip image. One colour is the same everywhere.
+    pub fn evenly_spaced(colours: Vec<Rgba<u8>>) -> Result<Gradient, String> {
+        let last = colours.len().saturating_sub(1).max(1) as f32;
+        let stops = colours
+            .into_iter()
+            .enumerate()
+            .map(|(index, colour)| (index as f32 / last, colour))
+            .collect();
+        Gradient::new(stops)
+    }
+
+    /// Read a gradient from `{"stops": [{"position": 0, "colour": [r, g, b]}, ...]}`.
+    pub fn from_json(json: &Value) -> Result<Gradient, String> {
+        let list = json
+            .get("stops")
+            .and_then(Value::as_array)
+            .ok_or("missing 'stops' list")?;
+        let stops = list
+            .iter()
+            .enumerate()
+            .map(|(index, stop)| {
+                let position = stop
+                    .get("position")
+                    .and_then(Value::as_f64)
+                    .ok_or_else(|| format!("stop {} needs a number 'position'", index + 1))?;
+                let colour = stop
+                    .get("colour")
+                    .ok_or_else(|| format!("stop {} needs a 'colour'", index + 1))?;
+                let colour = colour_from_json(colour, "colour")
+                    .map_err(|e| format!("stop {}: {}", index + 1, e))?;
+                Ok((position as f32, Rgba(colour)))
+            })
+            .collect::<Result<Vec<(f32, Rgba<u8>)>, String>>()?;
+        Gradient::new(stops)
+    }
+
+    /// The stops, sorted by position.
+    pub fn stops(&self) -> &[(f32, Rgba<u8>)] {
+        &self.stops
+    }
+
+    /// Colour at `t`, blending the stops either side.
+    pub fn sample(&self, t: f32) -> Rgba<u8> {
+        Rgba(self.sample_wide(t).map(|channel| channel.round() as u8))
+    }
+
+    /// Like `sample`, but without rounding the channels (still from 0 to 255), for output deeper than 8 bits.
+    pub fn sample_wide(&self, t: f32) -> [f32; 4] {
+        let stops = &self.stops;
+        let wide = |colour: Rgba<u8>| colour.0.map(|channel| channel as f32);
+        // The first stop past `t`. NaN is past nothing, so it gets the first colour like anything before the start
+        let next = stops.partition_point(|(position, _)| *position <= t);
+        if next == 0 {
+            return wide(stops[0].1);
+        }
+        if next == stops.len() {
+            return wide(stops[next - 1].1);
+        }
+        let ((p0, c0), (p1, c1)) = (stops[next - 1], stops[next]);
+        let fraction = (t - p0) / (p1 - p0); // p1 > p0, since stops at t's position come before `next`
+        let [a, b] = [wide(c0), wide(c1)];
+        std::array::from_fn(|i| a[i] + (b[i] - a[i]) * fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_match_the_stops_blend_between_them_and_clamp_past_the_ends() {
+        let gradient = Gradient::new(vec![
+            (0.2, rgb(0, 100, 200)),
+            (0.6, rgb(200, 50, 0)),
+            (1., rgb(100, 250, 40)),
+        ])
+        .unwrap();
+        // Exactly at a stop
+        assert_eq!(gradient.sample(0.2), rgb(0, 100, 200));
+        assert_eq!(gradient.sample(0.6), rgb(200, 50, 0));
+        assert_eq!(gradient.sample(1.), rgb(100, 250, 40));
+        // Halfway between two stops
+        assert_eq!(gradient.sample(0.4), rgb(100, 75, 100));
+        assert_eq!(gradient.sample(0.8), rgb(150, 150, 20));
+        // Before the first stop and past the last, the end colours carry on
+        for t in [-1., 0., 0.1] {
+            assert_eq!(gradient.sample(t), rgb(0, 100, 200), "at {}", t);
+        }
+        for t in [1.5, 10.] {
+            assert_eq!(gradient.sample(t), rgb(100, 250, 40), "at {}", t);
+        }
+        assert_eq!(gradient.sample(f32::NAN), rgb(0, 100, 200));
+    }
+
+    #[test]
+    fn stops_at_the_same_position_make_a_hard_edge() {
+        let gradient = Gradient::new(vec![
+            (0., rgb(0, 0, 0)),
+            (0.5, rgb(255, 0, 0)),
+            (0.5, rgb(0, 0, 255)),
+            (1., rgb(0, 0, 0)),
+        ])
+        .unwrap();
+        assert_eq!(gradient.sample(0.5), rgb(0, 0, 255));
+        assert_eq!(gradient.sample(0.5f32.next_down()), rgb(255, 0, 0));
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+
+/// A grayscale guide to where the iterations should go: white parts of the view get the full `max_iterations`
+/// and darker parts proportionally fewer, down to 1 at black. Usually much smaller than the render, and
+/// stretched over the whole view with bilinear filtering, so a few pixels are enough to pick out regions.
+pub struct ImportanceMap {
+    values: Vec<f32>, // Brightness from 0 to 1, in row-major order
+    width: u32,
+    height: u32,
+    pub source: Option<PathBuf>, // File the map was loaded from, so saved settings can refer to it
+}
+
+impl ImportanceMap {
+    /// Load a map from an image of any size. Colour images are converted to their luminance, and alpha is ignored.
+    pub fn from_image(path: &Path) -> Result<ImportanceMap, String> {
+        let img = image::open(path)
+            .map_err(|e| format!("failed to load importance map {}: {}", path.display(), e))?
+            .to_luma32f();
+        if img.width() == 0 || img.height() == 0 {
+            return Err(format!("importance map {} is empty", path.display()));
+        }
+        Ok(ImportanceMap {
+            width: img.width(),
+            height: img.height(),
+            values: img
+                .into_raw()
+                .into_iter()
+                .map(|v| v.clamp(0., 1.))
+                .collect(),
+            source: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Brightness at (u, v), each from 0 at the top left of the view to 1 at the bottom right, blending the
+    /// four nearest pixels. Past the centres of the edge pixels the edge colour carries on.
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        let axis = |t: f32, size: u32| {
+            let position = (t * size as f32 - 0.5).clamp(0., (size - 1) as f32);
+            let index = position.floor() as usize;
+            (
+                index,
+                (index + 1).min(size as usize - 1),
+                position - index as f32,
+            )
+        };
+        let (x0, x1, fx) = axis(u, self.width);
+        let (y0, y1, fy) = axis(v, self.height);
+        let at = |x: usize, y: usize| self.values[y * self.width as usize + x];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+        top + (bottom - top) * fy
+    }
+
+    /// Iteration limit at (u, v) out of `max_iterations`, proportional to the brightness there and at least 1.
+    pub fn iterations_at(&self, u: f32, v: f32, max_iterations: u32) -> u32 {
+        ((max_iterations as f32 * self.sample(u, v)).round() as u32).clamp(1, max_iterations.max(1))
+    }
+}
@@ -0,0 +1,45 @@
+//! Rendering of the Mandelbrot set and related escape-time fractals, shared by the viewer binary.
+//!
+//! The usual entry points are re-exported here: build a `MandelbrotSettings`, then `render` it to an image,
+//! or get the raw iteration counts from `render_counts` and colour them with `colorize`/`colorize_with`.
+//! `pixel_to_complex` and `complex_to_pixel` convert between pixel positions and the complex plane.
+//!
+//! Nothing here needs a window or GPU. With `--no-default-features` rayon is left out too and rendering runs on
+//! the calling thread, so the library builds for wasm32, where `mandelbrot_render` is exported for JS (see `wasm.rs`).
+
+extern crate image;
+extern crate num_complex;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+pub mod accumulate;
+pub mod area;
+#[cfg(not(target_arch = "wasm32"))] // Needs threads
+pub mod background;
+pub mod budget;
+pub mod controller;
+pub mod diff;
+pub mod flythrough;
+pub mod fractal;
+pub mod gradient;
+pub mod importance;
+pub mod json;
+pub mod mandelbrot_settings;
+pub mod montage;
+pub mod palette;
+mod parallel;
+pub mod render;
+pub mod symmetry;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+pub use controller::ViewController;
+pub use fractal::FractalKind;
+pub use gradient::Gradient;
+pub use mandelbrot_settings::MandelbrotSettings;
+pub use palette::Palette;
+pub use render::{
+    builtin_colour, builtin_colour_16, colorize, colorize_with, complex_to_pixel, pixel_to_complex,
+    render, render_16, render_counts, render_counts_region, render_region, render_region_16,
+    render_rgba, render_with_colorer, render_with_counts, Region,
+};
@@ -1,169 +1,1802 @@
 extern crate gfx_device_gl;
 extern crate image;
-extern crate num_complex;
+extern crate mandelbrot_test;
 extern crate piston_window;
-extern crate rayon;
 extern crate time;
+extern crate winit;
 
 use image::{ImageBuffer, Rgba}; // Image library
-use num_complex::Complex; // Complex number struct
+use num_complex::Complex;
 use piston_window::{
-    Image, MouseButton, MouseCursorEvent, PistonWindow, Texture, TextureSettings, WindowSettings,
+    clear, AdvancedWindow, Button, Image, Key, MouseButton, MouseCursorEvent, MouseScrollEvent,
+    PistonWindow, PressEvent, ReleaseEvent, ResizeEvent, TextEvent, Texture, TextureSettings,
+    Transformed, UpdateEvent, Window, WindowSettings,
 }; // Windowing library
-use rayon::prelude::*; // Parallel iterator
-use std::cell::RefCell; // Mutable reference cell
+use std::cell::{Cell, RefCell}; // Mutable reference cells
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::rc::Rc; // Reference counted pointer
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-
-// Import other files
+// Import other files. Rendering lives in the library, these are just the viewer's
+mod cli;
 mod click_handler;
-mod mandelbrot_settings;
+mod command;
+mod drag;
+mod overlay;
+mod session;
+use cli::{CliOptions, Subcommand};
 use click_handler::DoubleClickHandler;
-use mandelbrot_settings::MandelbrotSettings;
+use command::Command;
+use drag::DragPan;
+use mandelbrot_test::accumulate::Accumulator;
+use mandelbrot_test::area::AreaEstimate;
+use mandelbrot_test::budget::FrameBudget;
+use mandelbrot_test::diff::CountDiff;
+use mandelbrot_test::flythrough::{FlyPath, Waypoint};
+use mandelbrot_test::fractal::FractalKind;
+use mandelbrot_test::montage::contact_sheet;
+use mandelbrot_test::render::{self, render_with_counts, Region, ViewTransform};
+use mandelbrot_test::{json, MandelbrotSettings};
+use overlay::Probe;
+use session::Session;
+
+const CINEMATIC_ZOOM_RATE: f32 = 1.5; // Magnification per second while auto-zooming
+const EXPORT_FPS: f32 = 30.; // Nominal frame rate of exported auto-zoom frames
+/// Set once an image couldn't be shown, so the viewer closes as usual, saving the session, then reports failure.
+static GRAPHICS_LOST: AtomicBool = AtomicBool::new(false);
+/// Set when an upload only worked on a second try in a new texture context, so the other images are uploaded again.
+static CONTEXT_REPLACED: AtomicBool = AtomicBool::new(false);
+
+const ANIMATE_FRAMES: u32 = 90; // Frames `animate` renders of a zoom without `--frames`
 
 fn main() {
-    const WIDTH: u32 = 640;
-    const HEIGHT: u32 = 480;
-
-    let mut window: PistonWindow = WindowSettings::new("Mandelbrot!", [WIDTH, HEIGHT]) // Create a window builder object
-        .exit_on_esc(true)
-        .build() // Build the window
-        .unwrap(); // Unwrap the result. If it is an error, panic and crash. Otherwise, return the window
-
-    let settings = Rc::new(RefCell::new(MandelbrotSettings {
-        width: WIDTH,
-        height: HEIGHT,
-        max_iterations: 300,
-        zoom: 1.,
-        zoom_exp: 1.5,
-        offset_x: 0.0,
-        offset_y: 0.0,
-        gamma: 0.22
-    }));
+    const ZOOM_EXP_STEP: f32 = 0.1; // Amount `[` and `]` change the zoom step by
+    const SETTLE_TIME: Duration = Duration::from_millis(300); // Idle time after input before the full quality render
+    const PALETTE_BLEND_STEP: f32 = 0.1; // Amount `,` and `.` move the palette blend by
+    const COLOUR_OFFSET_STEP: f32 = 0.05; // Fraction of the ramp `I` and `O` rotate the colours by
+    const MAX_ACCUMULATED_SAMPLES: u32 = 64; // Past this, extra samples no longer visibly change the average
+    const ITERATION_SCROLL_FACTOR: f64 = 1.25; // Change in max iterations per notch of Ctrl+scroll
+    const JULIA_INSET_SCALE: u32 = 4; // The Julia inset is this many times smaller than the view
+    const JULIA_C_STEP: f32 = 0.005; // Amount Ctrl+arrows move the Julia constant by, small enough to see it morph
+
+    // Read the command line, skipping the program name. Subcommands share the usual flags. Without one, the
+    // flags decide, as before subcommands
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let subcommand = args.first().and_then(|name| Subcommand::from_name(name));
+    if subcommand.is_some() {
+        args.remove(0);
+    }
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", cli::usage(subcommand));
+        return;
+    }
+    // `batch` takes the file of views first
+    let batch_list = match subcommand {
+        Some(Subcommand::Batch) => match args.first().filter(|arg| !arg.starts_with("--")) {
+            Some(_) => Some(PathBuf::from(args.remove(0))),
+            None => {
+                eprintln!("batch needs a file of views, see 'mandelbrot_test batch --help'");
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    let mut options = match CliOptions::parse(args, subcommand) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+    options.batch = batch_list.or(options.batch.take());
+    let missing = match subcommand {
+        _ if options.print_settings => None, // Nothing will be written, so there's nowhere it needs to go
+        Some(Subcommand::Render) if options.output.is_none() && options.tiles_dir.is_none() => {
+            Some("render needs --output <file> or --tiles-dir <dir>")
+        }
+        Some(Subcommand::Animate) if options.frames_dir.is_none() => {
+            Some("animate needs --frames-dir <dir>")
+        }
+        Some(Subcommand::Diff) if options.files.len() != 2 => {
+            Some("diff needs two settings files, see 'mandelbrot_test diff --help'")
+        }
+        Some(Subcommand::Montage) if options.files.is_empty() || options.output.is_none() => {
+            Some("montage needs settings files and --output <sheet.png>")
+        }
+        _ => None,
+    };
+    if let Some(message) = missing {
+        eprintln!("{}", message);
+        std::process::exit(2);
+    }
+
+    // `diff` compares two renders and `montage` lays several out on one sheet, instead of showing one. Their
+    // files take the place of `--settings`, each with the environment and flags layered on top as usual
+    let compared = match subcommand {
+        Some(Subcommand::Diff) => Some(run_diff(&options).map(|identical| !identical as i32)),
+        Some(Subcommand::Montage) => Some(run_montage(&options).map(|()| 0)),
+        _ => None,
+    };
+    match compared {
+        Some(Ok(code)) => std::process::exit(code),
+        Some(Err(message)) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+        None => {}
+    }
+
+    // With `--resume`, pick up the last session. A missing or broken session file isn't fatal,
+    // it just means starting fresh.
+    let session_path = options.session_path();
+    let session = (session_path.as_ref())
+        .filter(|_| options.resume)
+        .map(|path| Session::load(path))
+        .and_then(|loaded| {
+            loaded
+                .map_err(|message| eprintln!("Starting a new session: {}", message))
+                .ok()
+        });
+
+    // Start from the session or a saved settings file if given, otherwise the defaults. Then layer the
+    // environment and finally the command line on top, so the most specific source wins.
+    let mut settings = match (&session, &options.settings) {
+        (Some(session), _) => session.settings.clone(),
+        (None, Some(path)) => match load_settings(path) {
+            Ok(settings) => settings,
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => MandelbrotSettings::default(),
+    };
+    cli::apply_env(&mut settings, |name| std::env::var(name).ok());
+    options.apply(&mut settings);
+    // Tiled renders only ever hold one tile in memory, so only a tile has to fit
+    let checked = match options.tiles_dir {
+        Some(_) => MandelbrotSettings {
+            width: settings.width.min(options.tile_size),
+            height: settings.height.min(options.tile_size),
+            ..settings.clone()
+        },
+        None => settings.clone(),
+    };
+    if let Err(message) = checked.check_size() {
+        eprintln!("{}", message);
+        std::process::exit(2);
+    }
+
+    // A dry run: show what would be rendered, after every default, file, variable and flag has been applied,
+    // in the same JSON as `--sidecar` so scripts can read it. Only the JSON goes to stdout
+    if options.print_settings {
+        println!("{}", settings.to_json().to_pretty_string());
+        return;
+    }
+
+    // Headless modes: render a zoom sequence, a batch of views, a directory of tiles or one file and exit
+    // without opening a window
+    if subcommand == Some(Subcommand::Animate) {
+        if let Err(message) = run_animate(settings, &options) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(list) = &options.batch {
+        match run_batch(&settings, list, &options) {
+            Ok(0) => return,
+            Ok(_) => std::process::exit(1), // Some views failed, already reported
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(dir) = &options.tiles_dir {
+        if let Err(message) = render_tiles_to_dir(&settings, dir, &options) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(output) = &options.output {
+        if let Err(message) = render_to_file(&settings, output, &options, options.region) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Without a working OpenGL context the window can't be built. Explain and point at headless
+    // mode, which only needs the CPU, rather than panicking.
+    let window_settings =
+        WindowSettings::new("Mandelbrot!", [settings.width, settings.height]).exit_on_esc(true); // Create a window builder object
+    let mut window: PistonWindow = match window_settings.build() {
+        Ok(window) => window,
+        Err(e) => {
+            eprintln!(
+                "Failed to open a window, OpenGL may not be available: {}",
+                e
+            );
+            eprintln!("Use --output <file> to render to an image without a window.");
+            std::process::exit(1);
+        }
+    };
+
+    // On high-DPI displays the window's framebuffer has more pixels than its logical size, so render at the
+    // framebuffer's resolution to stay sharp. The view covers the same area either way, since the zoom is
+    // relative to the view's size. Mouse positions and drawing are scaled between the two below.
+    let mut logical_size = [settings.width, settings.height];
+    let draw_size = window.draw_size();
+    let hidpi = (draw_size.width / window.size().width).max(1.);
+    if hidpi > 1. {
+        let physical = MandelbrotSettings {
+            width: draw_size.width as u32,
+            height: draw_size.height as u32,
+            ..settings.clone()
+        };
+        match physical.check_size() {
+            Ok(()) => settings = physical,
+            Err(message) => eprintln!("Rendering at the logical resolution: {}", message),
+        }
+    }
+    let hidpi = settings.width as f64 / logical_size[0] as f64; // 1 if the physical size was rejected
+
+    let initial_zoom = settings.zoom; // Depth is shown relative to where the session started
+    let settings = Rc::new(RefCell::new(settings));
 
     // Mouse position. Use Rc and RefCell to mutate the mouse position in the event loop
     let mouse_pos = Rc::new(RefCell::new([0.0, 0.0] as [f64; 2]));
+    // Recentre on the clicked pixel's own coordinate rather than the exact cursor position, toggled with `S`
+    let snap_clicks = Rc::new(Cell::new(
+        options.snap_clicks || session.as_ref().is_some_and(|s| s.snap_clicks),
+    ));
 
     // Clone the settings and mouse_pos to move them into the closures
     let settings_clone = Rc::clone(&settings);
     let mouse_pos_clone = Rc::clone(&mouse_pos);
+    let snap_clone = Rc::clone(&snap_clicks);
 
     // Double click handlers. Didn't realise it would get so complicated with borrowing, but good to learn.
     let left_click_callback = Box::new(move || {
         let mut settings = settings_clone.borrow_mut();
-        let [xi, yi] = mouse_to_screen(*mouse_pos_clone.borrow(), &*settings);
+        let [xi, yi] = mouse_to_screen(*mouse_pos_clone.borrow(), &settings, snap_clone.get());
         settings.offset_x = xi;
         settings.offset_y = yi;
-        settings.zoom = settings.zoom.clone() * settings.zoom_exp;
+        let zoom_exp = settings.zoom_exp;
+        if !settings.zoom_by(zoom_exp) {
+            eprintln!("Can't zoom in further: f32 precision limit reached");
+        }
         true
     });
 
     // Clone the settings and mouse_pos for moving to closures
     let settings_clone = Rc::clone(&settings);
     let mouse_pos_clone = Rc::clone(&mouse_pos);
+    let snap_clone = Rc::clone(&snap_clicks);
     let right_click_callback = Box::new(move || {
         let mut settings = settings_clone.borrow_mut();
-        let [xi, yi] = mouse_to_screen(*mouse_pos_clone.borrow(), &*settings);
+        let [xi, yi] = mouse_to_screen(*mouse_pos_clone.borrow(), &settings, snap_clone.get());
         settings.offset_x = xi;
         settings.offset_y = yi;
-        settings.zoom = settings.zoom.clone() / settings.zoom_exp;
+        let zoom_exp = settings.zoom_exp;
+        settings.zoom_by(1. / zoom_exp);
         true
     });
 
     // Create the click handlers
-    let mut left_click_handler = DoubleClickHandler::new(left_click_callback, MouseButton::Left, None);
-    let mut right_click_handler = DoubleClickHandler::new(right_click_callback, MouseButton::Right, None);
+    let mut left_click_handler = DoubleClickHandler::new(
+        left_click_callback,
+        MouseButton::Left,
+        options.double_click_ms,
+    );
+    let mut right_click_handler = DoubleClickHandler::new(
+        right_click_callback,
+        MouseButton::Right,
+        options.double_click_ms,
+    );
     let mut requires_recalculate: bool = false; // Flag to indicate if the image needs to be recalculated
+    let mut requires_status_update: bool = true; // Flag to indicate if the title overlay is stale
+    let mut requires_recolour: bool = false; // Flag to indicate if only the colouring of the image changed
+
+    // With a frame budget the previews' quality adapts to it, starting from `--preview-scale`
+    let mut frame_budget = options
+        .frame_budget_ms
+        .map(|ms| FrameBudget::new(Duration::from_millis(ms), options.preview_scale as f32));
+    let previews_available = options.preview_scale > 1
+        || settings.borrow().navigation_iterations.is_some()
+        || frame_budget.is_some();
+    // Render at low quality while navigating, toggled with `P`. Previews need a lower resolution or a
+    // navigation iteration cap to be any cheaper
+    let mut use_preview = session.as_ref().is_none_or(|s| s.use_preview) && previews_available;
+    let mut pending_full_render: Option<Instant> = None; // Time of the last input if only a preview is showing
+    let mut show_grid = session.as_ref().is_some_and(|s| s.show_grid); // Draw the axes and gridlines over the image, toggled with `A`
+    let mut show_heatmap = false; // Shade tiles by average iteration count, toggled with `H`
+    let mut show_area = false; // Show how much of the view is in the set, toggled with `U`
+    let mut probe: Option<Probe> = None; // Slice under the cursor to graph the counts of, cycled with `X`
+    let mut heatmap_opacity = options.heatmap_opacity; // Adjusted with `J` and `K`
+    let mut cinematic = false; // Continuously zoom towards the centre, toggled with `C`
+    let mut cinematic_anchor: Option<(Complex<f32>, [f32; 2])> = None; // Coordinate and pixel kept in place by Shift+C
+    let mut windowed_size: Option<[u32; 2]> = None; // Logical size to go back to, while fullscreen with F11
+    let mut drag = DragPan::new(!options.no_inertia && session.as_ref().is_none_or(|s| s.inertia)); // Left button drags, inertia toggled with `N`
+    let mut bookmarks = session
+        .as_ref()
+        .map(|s| s.bookmarks.clone())
+        .unwrap_or_default(); // Views saved with Ctrl+1 to 9
+    let mut history = session
+        .as_ref()
+        .map(|s| s.history.clone())
+        .unwrap_or_default(); // Views zoomed away from, for Backspace
+    let mut shown_view = Waypoint::of(&settings.borrow()); // View last rendered, pushed onto the history when a zoom leaves it
+    let mut going_back = false; // The next render is of a view taken off the history, so isn't pushed again
 
-    // Create a texture from the mandelbrot image to display initially
-    let mut image: Texture<gfx_device_gl::Resources> =
-        unwrap_image_to_texture(generate_mandelbrot_buffer(&*settings.borrow()), &mut window);
+    // Waypoints of the fly-through, added with `W`. Saved whenever they change, so `animate --tour` can render them
+    let tour_file = options
+        .tour
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("mandelbrot_tour.json"));
+    let mut tour = if tour_file.exists() {
+        FlyPath::load(&tour_file).unwrap_or_else(|message| {
+            eprintln!("Starting a new tour: {}", message);
+            FlyPath::default()
+        })
+    } else {
+        FlyPath::default()
+    };
+    let mut touring: Option<f32> = None; // How far through the tour its playback is, started with `Y`
+    let mut undrawn_timings: Option<(Duration, Duration)> = None; // Render and upload times of an image not yet drawn
+    let mut julia_inset: Option<Shown> = None; // Julia set picked with Shift+click
+    let mut measuring = false; // Clicks place measurement points instead of zooming, toggled with `M`
+    let mut measure_points: Vec<Complex<f32>> = Vec::new(); // Up to two clicked coordinates to measure between
+    let mut frozen = false; // Hold changes back instead of rendering them, toggled with `F`
+    let mut render_requested = false; // Render the held changes once while frozen, set by `R`
+    let mut frame_number = 0; // Index of the next exported auto-zoom frame
+    let mut ctrl_held = false; // Whether either Ctrl key is down
+    let mut shift_held = false; // Whether either Shift key is down
+    let mut pending_pan: Option<(i32, i32, MandelbrotSettings)> = None; // Whole pixels panned since the last render, and the view before
+    let mut goto_input: Option<String> = None; // Text typed after pressing `G`, while the go to prompt is open
+    let crossfade_time = Duration::from_millis(options.crossfade_ms);
+    let recolour_fade_time = Duration::from_millis(options.recolour_fade_ms);
+    let mut fading_from: Option<(Shown, Instant, Duration)> = None; // Image being faded out, when the fade began and how long it takes
+    let mut accumulate = session.as_ref().is_some_and(|s| s.accumulate); // Keep refining a static view with jittered samples, toggled with `T`
+    let mut accumulator: Option<Accumulator> = None; // Samples of the current view. Cleared whenever the image is replaced
+    let commands = options.commands.then(command::read_stdin_lines); // Lines typed or piped into stdin
+
+    // Create a texture from the mandelbrot image to display initially. The iteration counts of full quality
+    // renders are kept for analysis, and cleared while a preview is showing since they no longer match the view.
+    let (counts, buffer) = render_with_counts(&settings.borrow());
+    let mut displayed_counts: Option<Vec<f32>> = Some(counts);
+    let Some(mut image) = unwrap_image_to_texture(buffer, &mut window, &mut Duration::default())
+    else {
+        std::process::exit(1); // Nothing has changed yet, so there's no session to save
+    };
+    let mut last_rendered = Some(settings.borrow().to_json()); // Settings the showing image was rendered with
 
     // Event loop
     while let Some(event) = window.next() {
         // Update mouse position
         if let Some(pos) = event.mouse_cursor_args() {
-            *mouse_pos.borrow_mut() = pos;
+            *mouse_pos.borrow_mut() = pos.map(|p| p * hidpi); // In the image's pixels, not logical ones
+            if let Some(moved) = drag.move_to(*mouse_pos.borrow(), Instant::now()) {
+                drag_view(&mut settings.borrow_mut(), moved);
+                requires_recalculate = true;
+            }
+
+            // The probe follows the cursor. Negative positions saturate to the first row or column
+            let [x, y] = *mouse_pos.borrow();
+            probe = match probe {
+                Some(Probe::Row(_)) => Some(Probe::Row(y as u32)),
+                Some(Probe::Column(_)) => Some(Probe::Column(x as u32)),
+                None => None,
+            };
+        }
+
+        // Render at the window's new size when it's resized or goes fullscreen, keeping the same span of the
+        // plane across. The first resize after opening is to the size it already has, so changes nothing
+        if let Some(args) = event.resize_args() {
+            let size = args.window_size.map(|s| s.round().max(1.) as u32);
+            if size != logical_size {
+                let resized = MandelbrotSettings {
+                    width: (size[0] as f64 * hidpi).round() as u32,
+                    height: (size[1] as f64 * hidpi).round() as u32,
+                    ..settings.borrow().clone()
+                };
+                match resized.check_size() {
+                    Ok(()) => {
+                        *settings.borrow_mut() = resized;
+                        logical_size = size;
+                        requires_recalculate = true;
+                    }
+                    Err(message) => eprintln!("Keeping the old resolution: {}", message),
+                }
+            }
+        }
+
+        // Track Ctrl, which turns clicks into coordinate probes, and Shift, which turns them into Julia picks
+        match (event.press_args(), event.release_args()) {
+            (Some(Button::Keyboard(Key::LCtrl | Key::RCtrl)), _) => ctrl_held = true,
+            (_, Some(Button::Keyboard(Key::LCtrl | Key::RCtrl))) => ctrl_held = false,
+            (Some(Button::Keyboard(Key::LShift | Key::RShift)), _) => shift_held = true,
+            (_, Some(Button::Keyboard(Key::LShift | Key::RShift))) => shift_held = false,
+            _ => {}
+        }
+
+        // Handle clicks. Ctrl+click prints the exact coordinate under the cursor instead, and doesn't count
+        // towards a double click, so it can never trigger a zoom.
+        if ctrl_held {
+            if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+                let [x, y] = *mouse_pos.borrow();
+                let c = render::pixel_to_complex(&settings.borrow(), x as f32, y as f32);
+                println!("{} {}", c.re, c.im); // Shortest form that round-trips, so it can be pasted into `G`
+            }
+        } else if shift_held {
+            // Shift+click shows the Julia set of the clicked point in the corner, Shift+right click hides it.
+            // The Mandelbrot set is the map of which Julia sets are connected, so only its view offers them
+            match event.press_args() {
+                Some(Button::Mouse(MouseButton::Left))
+                    if settings.borrow().fractal == FractalKind::Mandelbrot =>
+                {
+                    let settings = settings.borrow();
+                    let [x, y] = *mouse_pos.borrow();
+                    let c = render::pixel_to_complex(&settings, x as f32, y as f32);
+                    let julia = MandelbrotSettings {
+                        width: (settings.width / JULIA_INSET_SCALE).max(1),
+                        height: (settings.height / JULIA_INSET_SCALE).max(1),
+                        fractal: FractalKind::Julia,
+                        julia_c: c,
+                        zoom: 1.,
+                        offset_x: 0.,
+                        offset_y: 0.,
+                        supersample: 1,
+                        view_transform: ViewTransform::Linear,
+                        ..settings.clone()
+                    };
+                    let buffer = render::render(&julia);
+                    julia_inset =
+                        unwrap_image_to_texture(buffer, &mut window, &mut Duration::default());
+                    println!("Julia set of {} {}", c.re, c.im);
+                }
+                Some(Button::Mouse(MouseButton::Right)) => julia_inset = None,
+                _ => {}
+            }
+        } else if measuring {
+            // Two clicks give the points to measure between, and a third clears them for a new measurement
+            if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+                if measure_points.len() == 2 {
+                    measure_points.clear();
+                } else {
+                    let [x, y] = *mouse_pos.borrow();
+                    measure_points.push(render::pixel_to_complex(
+                        &settings.borrow(),
+                        x as f32,
+                        y as f32,
+                    ));
+                    if let [a, b] = measure_points[..] {
+                        println!("distance: {}", (b - a).norm());
+                    }
+                }
+                requires_status_update = true;
+            }
+        } else {
+            if let Some(Button::Mouse(MouseButton::Left)) = event.press_args() {
+                drag.press(*mouse_pos.borrow(), Instant::now());
+            }
+            requires_recalculate |= left_click_handler.handle_if_button_pressed(&event);
+            requires_recalculate |= right_click_handler.handle_if_button_pressed(&event);
+        }
+        // A drag ends whatever else is held by then. It was no click, so the next click can't make it a double
+        if let Some(Button::Mouse(MouseButton::Left)) = event.release_args() {
+            if drag.release(Instant::now()) {
+                left_click_handler.forget_click();
+            }
+        }
+
+        // Ctrl+scroll scales the iteration limit, for tuning quality without leaving the mouse. Scrolling
+        // without Ctrl is left alone, so it can't change the iterations by accident
+        if let Some([_, notches]) = event.mouse_scroll_args().filter(|_| ctrl_held) {
+            let mut settings = settings.borrow_mut();
+            let old = settings.max_iterations;
+            let scaled = (old as f64 * ITERATION_SCROLL_FACTOR.powf(notches)).round();
+            // Always move by at least one, so small limits can't get stuck
+            settings.max_iterations = match scaled as u32 {
+                new if notches > 0. => new.max(old.saturating_add(1)),
+                new if notches < 0. => new.min(old.saturating_sub(1)).max(1),
+                _ => old,
+            };
+            requires_recalculate |= settings.max_iterations != old;
+            requires_status_update = true;
         }
 
-        // Handle clicks
-        requires_recalculate |= left_click_handler.handle_if_button_pressed(&event);
-        requires_recalculate |= right_click_handler.handle_if_button_pressed(&event);
+        // While the go to prompt is open, keys edit the typed text instead of triggering their usual actions
+        if let Some(input) = &mut goto_input {
+            if let Some(text) = event.text_args() {
+                input.extend(text.chars().filter(|c| is_goto_char(*c))); // Also drops the `g` that opened the prompt
+                requires_status_update = true;
+            }
+            if let Some(Button::Keyboard(key)) = event.press_args() {
+                match key {
+                    Key::Return | Key::NumPadEnter => {
+                        if let Err(message) = settings.borrow_mut().go_to(input) {
+                            eprintln!("Ignoring go to '{}': {}", input, message);
+                        } else {
+                            requires_recalculate = true;
+                        }
+                        goto_input = None;
+                    }
+                    Key::Backspace => {
+                        input.pop();
+                    }
+                    Key::G => goto_input = None,
+                    _ => {}
+                }
+                requires_status_update = true;
+            }
+        } else if let Some(Button::Keyboard(key)) = event.press_args() {
+            // Handle key presses. Changing the zoom step doesn't change the image, so only the overlay needs updating
+            let mut settings = settings.borrow_mut();
+            match key {
+                Key::RightBracket => {
+                    let zoom_exp = settings.zoom_exp + ZOOM_EXP_STEP;
+                    settings.set_zoom_exp(zoom_exp);
+                    requires_status_update = true;
+                }
+                Key::LeftBracket => {
+                    let zoom_exp = settings.zoom_exp - ZOOM_EXP_STEP;
+                    settings.set_zoom_exp(zoom_exp);
+                    requires_status_update = true;
+                }
+                Key::Equals | Key::Plus | Key::NumPadPlus | Key::PageUp => {
+                    // Zoom on the centre of the view, like a double click there
+                    let zoom_exp = settings.zoom_exp;
+                    if settings.zoom_by(zoom_exp) {
+                        requires_recalculate = true;
+                    } else {
+                        eprintln!("Can't zoom in further: f32 precision limit reached");
+                    }
+                }
+                Key::Minus | Key::NumPadMinus | Key::PageDown => {
+                    let zoom_exp = settings.zoom_exp;
+                    requires_recalculate |= settings.zoom_by(1. / zoom_exp);
+                }
+                Key::Comma | Key::Period if settings.blend_palette.is_some() => {
+                    let step = if key == Key::Comma {
+                        -PALETTE_BLEND_STEP
+                    } else {
+                        PALETTE_BLEND_STEP
+                    };
+                    // Snap to whole steps, so repeated presses land exactly on 0 and 1
+                    let blend = ((settings.palette_blend + step) / PALETTE_BLEND_STEP).round()
+                        * PALETTE_BLEND_STEP;
+                    settings.palette_blend = blend.clamp(0., 1.);
+                    requires_recolour = true;
+                }
+                Key::I | Key::O => {
+                    let step = if key == Key::I {
+                        -COLOUR_OFFSET_STEP
+                    } else {
+                        COLOUR_OFFSET_STEP
+                    };
+                    // Snap to whole steps, wrapping so the colours keep rotating
+                    let steps = (1. / COLOUR_OFFSET_STEP).round();
+                    let offset = ((settings.colour_offset + step) / COLOUR_OFFSET_STEP).round();
+                    settings.colour_offset = offset.rem_euclid(steps) * COLOUR_OFFSET_STEP;
+                    requires_recolour = true;
+                    requires_status_update = true;
+                }
+                Key::G => {
+                    goto_input = Some(String::new());
+                    requires_status_update = true;
+                }
+                Key::Backspace => {
+                    // Back to where the last zoom started from
+                    if let Some(view) = history.pop() {
+                        match settings.set_view(view.offset_x, view.offset_y, view.zoom) {
+                            Ok(()) => {
+                                requires_recalculate = true;
+                                going_back = true;
+                            }
+                            Err(message) => eprintln!("Can't go back: {}", message),
+                        }
+                    }
+                }
+                Key::D1
+                | Key::D2
+                | Key::D3
+                | Key::D4
+                | Key::D5
+                | Key::D6
+                | Key::D7
+                | Key::D8
+                | Key::D9 => {
+                    // Ctrl+digit saves the view on that key, and the digit alone jumps back to it
+                    let slot = (key.code() - Key::D0.code()) as u8;
+                    if ctrl_held {
+                        let view = Waypoint::of(&settings);
+                        bookmarks.insert(slot, view);
+                        println!(
+                            "Bookmark {}: {} {} {}",
+                            slot, view.offset_x, view.offset_y, view.zoom
+                        );
+                    } else if let Some(view) = bookmarks.get(&slot) {
+                        match settings.set_view(view.offset_x, view.offset_y, view.zoom) {
+                            Ok(()) => requires_recalculate = true,
+                            Err(message) => eprintln!("Can't go to bookmark {}: {}", slot, message),
+                        }
+                    }
+                }
+                Key::D => {
+                    // Recentre on the most detailed boundary point, once a full quality render is showing
+                    // The counts are at the supersampled resolution, so map them with those settings
+                    let rendered = settings.supersampled();
+                    let deepest = displayed_counts.as_ref().and_then(|counts| {
+                        render::deepest_escaped_pixel(
+                            counts,
+                            rendered.width,
+                            rendered.max_iterations,
+                        )
+                    });
+                    if let Some((x, y)) = deepest {
+                        let c = render::pixel_to_complex(&rendered, x as f32, y as f32);
+                        settings.offset_x = c.re;
+                        settings.offset_y = c.im;
+                        requires_recalculate = true;
+                    }
+                }
+                Key::Left | Key::Right | Key::Up | Key::Down
+                    if ctrl_held && settings.fractal == FractalKind::Julia =>
+                {
+                    // Nudge the Julia constant instead of panning: left and right move the real part, up and down the imaginary
+                    let (dre, dim) = match key {
+                        Key::Left => (-JULIA_C_STEP, 0.),
+                        Key::Right => (JULIA_C_STEP, 0.),
+                        Key::Up => (0., JULIA_C_STEP),
+                        _ => (0., -JULIA_C_STEP),
+                    };
+                    settings.julia_c += Complex::new(dre, dim);
+                    requires_recalculate = true;
+                }
+                Key::Left | Key::Right | Key::Up | Key::Down => {
+                    // Pan a tenth of the view, in whole pixels so the rest of the image can be reused
+                    let (step_x, step_y) = (
+                        (settings.width / 10).max(1) as i32,
+                        (settings.height / 10).max(1) as i32,
+                    );
+                    let (dx, dy) = match key {
+                        Key::Left => (-step_x, 0),
+                        Key::Right => (step_x, 0),
+                        Key::Up => (0, -step_y),
+                        _ => (0, step_y),
+                    };
+                    let (total_x, total_y, before) = pending_pan
+                        .take()
+                        .unwrap_or_else(|| (0, 0, settings.clone()));
+                    settings.pan_pixels(dx, dy);
+                    pending_pan = Some((total_x + dx, total_y + dy, before));
+                }
+                Key::A => show_grid = !show_grid,
+                Key::S => snap_clicks.set(!snap_clicks.get()),
+                Key::N => drag.inertia = !drag.inertia,
+                Key::H => show_heatmap = !show_heatmap,
+                Key::U => {
+                    show_area = !show_area;
+                    requires_status_update = true;
+                }
+                Key::X => {
+                    // Off, then a row, then a column, starting under the cursor
+                    let [x, y] = *mouse_pos.borrow();
+                    probe = match probe {
+                        None => Some(Probe::Row(y as u32)),
+                        Some(Probe::Row(_)) => Some(Probe::Column(x as u32)),
+                        Some(Probe::Column(_)) => None,
+                    };
+                }
+                Key::J | Key::K if show_heatmap => {
+                    // Step in tenths, snapping so repeated steps land exactly on 0 and 1
+                    let step = if key == Key::J { -1. } else { 1. };
+                    heatmap_opacity = ((heatmap_opacity * 10.).round() + step).clamp(0., 10.) / 10.;
+                }
+                Key::V => {
+                    settings.view_transform = match settings.view_transform {
+                        ViewTransform::Linear => ViewTransform::Exponential,
+                        ViewTransform::Exponential => ViewTransform::Sphere,
+                        ViewTransform::Sphere => ViewTransform::Linear,
+                    };
+                    requires_recalculate = true;
+                }
+                Key::L => {
+                    settings.contours = !settings.contours;
+                    requires_recolour = true;
+                }
+                Key::E => {
+                    settings.edge_blend = !settings.edge_blend;
+                    requires_recolour = true;
+                }
+                Key::W if shift_held => {
+                    if tour.waypoints.pop().is_some() {
+                        save_tour(&tour, &tour_file);
+                    }
+                    print_tour(&tour);
+                }
+                Key::W => {
+                    tour.waypoints.push(Waypoint::of(&settings));
+                    save_tour(&tour, &tour_file);
+                    print_tour(&tour);
+                }
+                Key::Y => {
+                    touring = match touring {
+                        Some(_) => None,
+                        None if tour.waypoints.len() < 2 => {
+                            eprintln!("A tour needs at least 2 waypoints, add views with W");
+                            None
+                        }
+                        None => match tour.view_at(&settings, 0.) {
+                            // Start from the first waypoint, so exported tours begin exactly there
+                            Ok(view) => {
+                                (settings.offset_x, settings.offset_y) =
+                                    (view.offset_x, view.offset_y);
+                                settings.zoom = view.zoom;
+                                cinematic = false;
+                                requires_recalculate = true;
+                                Some(0.)
+                            }
+                            Err(message) => {
+                                eprintln!("Can't start the tour: {}", message);
+                                None
+                            }
+                        },
+                    };
+                    requires_status_update = true;
+                }
+                Key::M => {
+                    measuring = !measuring;
+                    measure_points.clear();
+                    requires_status_update = true;
+                }
+                Key::F11 => {
+                    // The resize that follows picks up the new size. Leaving fullscreen asks for the old size
+                    // back too, as not every platform restores it by itself
+                    let winit_window = &window.window.window;
+                    match windowed_size.take() {
+                        Some([width, height]) => {
+                            winit_window.set_fullscreen(None);
+                            winit_window
+                                .set_inner_size(winit::dpi::LogicalSize::new(width, height));
+                        }
+                        None => {
+                            windowed_size = Some(logical_size);
+                            let monitor = winit::window::Fullscreen::Borderless(None); // The current one
+                            winit_window.set_fullscreen(Some(monitor));
+                        }
+                    }
+                }
+                Key::F => {
+                    frozen = !frozen;
+                    requires_status_update = true;
+                }
+                Key::R if frozen => render_requested = true,
+                Key::T => {
+                    accumulate = !accumulate;
+                    accumulator = None;
+                    requires_status_update = true;
+                }
+                Key::C => {
+                    cinematic = !cinematic;
+                    touring = None;
+                    // With Shift, zoom towards the point under the cursor instead, keeping it pinned there
+                    cinematic_anchor = None;
+                    if cinematic && shift_held && settings.view_transform == ViewTransform::Linear {
+                        let [x, y] = mouse_pos.borrow().map(|p| p as f32);
+                        let target = render::pixel_to_complex(&settings, x, y);
+                        cinematic_anchor = Some((target, [x, y]));
+                    }
+                }
+                Key::P => {
+                    use_preview = !use_preview && previews_available;
+                    requires_status_update = true;
+                }
+                _ => {}
+            }
+        }
+
+        // Apply commands from stdin. Changes only show on `render`, so a script can make several at once
+        while let Some(line) = commands.as_ref().and_then(|lines| lines.try_recv().ok()) {
+            let mut settings = settings.borrow_mut();
+            let result = Command::parse(&line).and_then(|command| match command {
+                Command::Zoom(zoom) => {
+                    let (x, y) = (settings.offset_x, settings.offset_y);
+                    settings.set_view(x, y, zoom)
+                }
+                Command::Center(x, y) => {
+                    let zoom = settings.zoom;
+                    settings.set_view(x, y, zoom)
+                }
+                Command::Iterations(iterations) => {
+                    settings.max_iterations = iterations;
+                    Ok(())
+                }
+                Command::Save(path) => render_to_file(&settings, &path, &options, None),
+                Command::Render => {
+                    requires_recalculate = true;
+                    Ok(())
+                }
+                Command::Waypoints => {
+                    print_tour(&tour);
+                    Ok(())
+                }
+                Command::Remove(index) => tour.remove(index).map(|_| {
+                    save_tour(&tour, &tour_file);
+                    print_tour(&tour);
+                }),
+                Command::Move(from, to) => tour.move_waypoint(from, to).map(|()| {
+                    save_tour(&tour, &tour_file);
+                    print_tour(&tour);
+                }),
+                Command::Area(samples) => {
+                    let estimate = AreaEstimate::monte_carlo(&settings, samples, 0);
+                    println!("{}", area_text(&estimate, &settings, true));
+                    Ok(())
+                }
+            });
+            if let Err(message) = result {
+                eprintln!("{}: {}", line.trim(), message);
+            }
+            requires_status_update = true;
+        }
+
+        // Auto-zoom by a fixed rate per second, so the speed doesn't depend on how fast frames render.
+        // When exporting, each frame is a fixed step instead, so slow renders don't drop frames.
+        if let Some(args) = event.update_args() {
+            let dt = match options.frames_dir {
+                Some(_) => 1. / EXPORT_FPS,
+                None => args.dt as f32,
+            };
+            // A released drag glides on, rendering previews as it goes until it stops and the view settles
+            if let Some(moved) = drag.glide(args.dt) {
+                drag_view(&mut settings.borrow_mut(), moved);
+                requires_recalculate = true;
+            }
+            if cinematic {
+                let factor = CINEMATIC_ZOOM_RATE.powf(dt);
+                let zoomed = match cinematic_anchor {
+                    Some((target, pixel)) => {
+                        settings.borrow_mut().zoom_about(target, pixel, factor)
+                    }
+                    None => settings.borrow_mut().zoom_by(factor),
+                };
+                if zoomed {
+                    requires_recalculate = true;
+                } else {
+                    eprintln!("Stopping auto-zoom: f32 precision limit reached");
+                    cinematic = false;
+                    requires_status_update = true;
+                }
+            }
+            // The tour flies at the same pace, and stops once the last waypoint has been shown
+            if let Some(progress) = touring {
+                touring = match progress {
+                    1. => None,
+                    _ => {
+                        let progress = (progress + dt / tour_seconds(&tour)).min(1.);
+                        let mut settings = settings.borrow_mut();
+                        match tour.view_at(&settings, progress) {
+                            Ok(view) => {
+                                (settings.offset_x, settings.offset_y) =
+                                    (view.offset_x, view.offset_y);
+                                settings.zoom = view.zoom;
+                                requires_recalculate = true;
+                                Some(progress)
+                            }
+                            Err(message) => {
+                                eprintln!("Stopping the tour: {}", message);
+                                None
+                            }
+                        }
+                    }
+                };
+                requires_status_update = true;
+            }
+        }
+
+        // While frozen, changes pile up in the flags below until `R` renders them all at once
+        let held = frozen && !std::mem::take(&mut render_requested);
+
+        // Time the renders and texture uploads below, for `--timings`. Uploads add to `upload_time` as they go,
+        // and the rest of the section is rendering
+        let section_start = Instant::now();
+        let mut upload_time = Duration::ZERO;
+
+        // After a pan, only the newly exposed strips need rendering if the previous full render is still showing
+        if let Some((dx, dy, before)) = pending_pan.take_if(|_| !held) {
+            match displayed_counts.take().filter(|_| !requires_recalculate) {
+                Some(previous) => {
+                    let (counts, buffer) = render::render_with_counts_panned(
+                        &settings.borrow(),
+                        &before,
+                        &previous,
+                        dx,
+                        dy,
+                    );
+                    let Some(texture) =
+                        unwrap_image_to_texture(buffer, &mut window, &mut upload_time)
+                    else {
+                        continue;
+                    };
+                    image = texture;
+                    displayed_counts = Some(counts);
+                    fading_from = None;
+                    accumulator = None;
+                    last_rendered = Some(settings.borrow().to_json());
+                    shown_view = Waypoint::of(&settings.borrow());
+                    requires_status_update = true;
+                }
+                None => requires_recalculate = true,
+            }
+        }
 
-        // Recalculate if necessary
-        if requires_recalculate {
-            let buffer = generate_mandelbrot_buffer(&*settings.borrow());
-            image = unwrap_image_to_texture(buffer, &mut window);
+        // A change to only the colouring is recoloured from the counts showing, without iterating, and fades in
+        // over the old colouring. A preview has no counts to recolour, and other changes need a render anyway
+        if requires_recolour && !held {
+            requires_recolour = false;
+            match displayed_counts.as_ref().filter(|_| !requires_recalculate) {
+                Some(counts) => {
+                    let settings = settings.borrow();
+                    let buffer = render::recolorize(counts, &settings);
+                    let Some(texture) =
+                        unwrap_image_to_texture(buffer, &mut window, &mut upload_time)
+                    else {
+                        continue;
+                    };
+                    let old = std::mem::replace(&mut image, texture);
+                    // Fading from a fade that hasn't finished jumps to the newest image, as the preview fades do
+                    fading_from = (!recolour_fade_time.is_zero())
+                        .then(|| (old, Instant::now(), recolour_fade_time));
+                    accumulator = None;
+                    last_rendered = Some(settings.to_json());
+                    requires_status_update = true;
+                }
+                None => requires_recalculate = true,
+            }
+        }
+
+        // Skip the render if nothing actually changed since the last one, e.g. going to the current view.
+        // The JSON form covers every setting that affects the image, so comparing it is comparing the views.
+        if requires_recalculate && !held {
+            let snapshot = settings.borrow().to_json();
+            if last_rendered.as_ref() == Some(&snapshot) {
+                requires_recalculate = false;
+            } else {
+                last_rendered = Some(snapshot);
+            }
+            // Remember the view a zoom left, for Backspace. Pans alone and the auto-zoom's and tour's frames
+            // aren't worth going back through one at a time
+            let view = Waypoint::of(&settings.borrow());
+            if view.zoom != shown_view.zoom && !going_back && !cinematic && touring.is_none() {
+                if history.len() == session::MAX_HISTORY {
+                    history.remove(0);
+                }
+                history.push(shown_view);
+            }
+            shown_view = view;
+            going_back = false;
+        }
+
+        // Recalculate if necessary. While navigating only a quick preview is rendered, and the
+        // full quality render waits until the input has settled.
+        if held {
+            // Nothing is rendered, not even the settle or accumulation passes, which would show the held changes
+        } else if requires_recalculate {
+            let settings = settings.borrow();
+            fading_from = None; // A new image is about to replace whatever was fading
+            accumulator = None;
+            if let Some(frames_dir) =
+                (options.frames_dir.as_ref()).filter(|_| cinematic || touring.is_some())
+            {
+                // Exported frames are always full quality
+                let (counts, buffer) = render_with_counts(&settings);
+                displayed_counts = Some(counts);
+                let path = frames_dir.join(format!("frame_{:05}.png", frame_number));
+                if let Err(e) = std::fs::create_dir_all(frames_dir)
+                    .and_then(|_| buffer.save(&path).map_err(std::io::Error::other))
+                {
+                    eprintln!(
+                        "Stopping auto-zoom, failed to save {}: {}",
+                        path.display(),
+                        e
+                    );
+                    cinematic = false;
+                    touring = None;
+                }
+                frame_number += 1;
+                let Some(texture) = unwrap_image_to_texture(buffer, &mut window, &mut upload_time)
+                else {
+                    continue;
+                };
+                image = texture;
+                pending_full_render = None;
+            } else if use_preview {
+                let preview = match &frame_budget {
+                    Some(budget) => budget.settings_for(&settings),
+                    None => settings.preview(options.preview_scale),
+                };
+                let start = Instant::now();
+                let buffer = render::render(&preview);
+                let Some(texture) = unwrap_image_to_texture(buffer, &mut window, &mut upload_time)
+                else {
+                    continue;
+                };
+                image = texture;
+                // The upload goes with the pixel count too, so it counts towards the frame's time
+                if let Some(budget) = frame_budget.as_mut() {
+                    budget.record(start.elapsed());
+                }
+                displayed_counts = None;
+                pending_full_render = Some(Instant::now());
+            } else {
+                let (counts, buffer) = render_with_counts(&settings);
+                let Some(texture) = unwrap_image_to_texture(buffer, &mut window, &mut upload_time)
+                else {
+                    continue;
+                };
+                image = texture;
+                displayed_counts = Some(counts);
+                pending_full_render = None;
+            }
             requires_recalculate = false;
+            requires_status_update = true;
+        } else if pending_full_render.is_some_and(|last_input| last_input.elapsed() >= SETTLE_TIME)
+        {
+            let (counts, buffer) = render_with_counts(&settings.borrow());
+            let Some(texture) = unwrap_image_to_texture(buffer, &mut window, &mut upload_time)
+            else {
+                continue;
+            };
+            let preview = std::mem::replace(&mut image, texture);
+            if !crossfade_time.is_zero() {
+                fading_from = Some((preview, Instant::now(), crossfade_time));
+            }
+            displayed_counts = Some(counts);
+            pending_full_render = None;
+            accumulator = None;
+            requires_status_update = show_area; // The title's area is counted from the new counts
+        } else if accumulate
+            && event.update_args().is_some()
+            && pending_full_render.is_none()
+            && !cinematic
+            && accumulator
+                .as_ref()
+                .is_none_or(|acc| acc.samples < MAX_ACCUMULATED_SAMPLES)
+        {
+            // The view is static and at full quality, so refine it with one more sample per update.
+            // Only the image is averaged; the counts stay those of the unjittered render.
+            let settings = settings.borrow();
+            let acc = accumulator
+                .get_or_insert_with(|| Accumulator::new(settings.width, settings.height));
+            acc.add(&render::render(&settings.jittered(acc.next_jitter())));
+            let Some(texture) =
+                unwrap_image_to_texture(acc.average(), &mut window, &mut upload_time)
+            else {
+                continue;
+            };
+            image = texture;
+            requires_status_update = true;
+        }
+
+        // An upload that only worked in a new texture context may have left the other images in the old one, where
+        // they might no longer draw, so they're uploaded again from their pixels
+        if CONTEXT_REPLACED.swap(false, Ordering::Relaxed) {
+            let fading = fading_from.iter_mut().map(|(old, _, _)| old);
+            let mut showing = std::iter::once(&mut image)
+                .chain(fading)
+                .chain(julia_inset.iter_mut());
+            if !showing.all(|shown| reupload(shown, &mut window)) {
+                continue;
+            }
+        }
+
+        if !upload_time.is_zero() {
+            // A new image was shown, so report it once it's been drawn
+            let render_time = section_start.elapsed().saturating_sub(upload_time);
+            undrawn_timings = Some((render_time, upload_time));
+        }
+
+        // Drop the old image once the fade has finished
+        if fading_from
+            .as_ref()
+            .is_some_and(|(_, start, duration)| start.elapsed() >= *duration)
+        {
+            fading_from = None;
+        }
+
+        // Show the current view in the title bar, which acts as a lightweight overlay
+        if requires_status_update {
+            let title = match &goto_input {
+                Some(input) => format!("Go to x y [zoom], Enter to jump, G to cancel: {}_", input),
+                None => {
+                    let mut text = status_text(&settings.borrow(), initial_zoom, use_preview);
+                    if frozen {
+                        text += " | frozen, R to render";
+                    }
+                    if let Some(acc) = accumulator.as_ref().filter(|_| accumulate) {
+                        text += &format!(" | {} samples", acc.samples);
+                    }
+                    if let Some(progress) = touring {
+                        text += &format!(" | touring: {:.0}%", progress * 100.);
+                    }
+                    if measuring {
+                        text += &measure_text(&settings.borrow(), &measure_points);
+                    }
+                    if show_area {
+                        // Counted from the full render's counts, so there's nothing to show over a preview
+                        text += &match &displayed_counts {
+                            Some(counts) => {
+                                let settings = settings.borrow();
+                                let estimate = AreaEstimate::from_counts(counts, &settings);
+                                format!(" | {}", area_text(&estimate, &settings, false))
+                            }
+                            None => " | set: waiting for the full render".to_string(),
+                        };
+                    }
+                    text
+                }
+            };
+            window.set_title(title);
+            requires_status_update = false;
         }
 
-        // Draw
-        window.draw_2d(&event, |context, graphics, _| {
-            Image::new().draw(&image, &Default::default(), context.transform, graphics);
+        // Draw, stretching the texture over the window so previews fill it too. Overlays are drawn on top
+        // rather than into the buffer, so they never end up in saved images.
+        let settings = settings.borrow();
+        let [width, height] = [settings.width as f64, settings.height as f64];
+        let draw_start = Instant::now();
+        let drawn = window.draw_2d(&event, |context, graphics, _| {
+            let context = context.scale(1. / hidpi, 1. / hidpi); // Draw in the image's pixels
+            clear(options.background, graphics);
+            let rect = [0., 0., width, height];
+            // While fading, draw the old image underneath and the new one on top with rising opacity
+            let alpha = match &fading_from {
+                Some((old, start, duration)) => {
+                    Image::new().rect(rect).draw(
+                        &old.texture,
+                        &Default::default(),
+                        context.transform,
+                        graphics,
+                    );
+                    start.elapsed().as_secs_f32() / duration.as_secs_f32()
+                }
+                None => 1.,
+            };
+            Image::new()
+                .rect(rect)
+                .color([1., 1., 1., alpha.min(1.)])
+                .draw(
+                    &image.texture,
+                    &Default::default(),
+                    context.transform,
+                    graphics,
+                );
+            // The heatmap needs the counts, so it's hidden while a preview is showing
+            if let Some(counts) = displayed_counts.as_ref().filter(|_| show_heatmap) {
+                overlay::draw_heatmap(counts, &settings, heatmap_opacity, context, graphics);
+            }
+            if show_grid {
+                overlay::draw_grid(&settings, context, graphics);
+            }
+            if let (Some(counts), Some(probe)) = (&displayed_counts, probe) {
+                overlay::draw_probe(counts, &settings, probe, context, graphics);
+            }
+            overlay::draw_measurement(&settings, &measure_points, context, graphics);
+            // The Julia inset goes in the top right corner, over everything else
+            if let Some(inset) = &julia_inset {
+                let scale = JULIA_INSET_SCALE as f64;
+                let inset_rect = [width - width / scale, 0., width / scale, height / scale];
+                Image::new().rect(inset_rect).draw(
+                    &inset.texture,
+                    &Default::default(),
+                    context.transform,
+                    graphics,
+                );
+            }
         });
+        if let Some((render_time, upload_time)) = undrawn_timings.filter(|_| drawn.is_some()) {
+            if options.timings {
+                println!(
+                    "render: {:.1?} | texture upload: {:.1?} | draw: {:.1?}",
+                    render_time,
+                    upload_time,
+                    draw_start.elapsed()
+                );
+            }
+            undrawn_timings = None;
+        }
+    }
+
+    // Remember where we were, for `--resume`. The window size is saved in logical pixels, so the next window
+    // opens at the same size, and as the size before going fullscreen if it still is
+    let [width, height] = windowed_size.unwrap_or(logical_size);
+    let session = Session {
+        settings: MandelbrotSettings {
+            width,
+            height,
+            ..settings.borrow().clone()
+        },
+        bookmarks,
+        history,
+        show_grid,
+        use_preview,
+        accumulate,
+        snap_clicks: snap_clicks.get(),
+        inertia: drag.inertia,
+    };
+    if let Some(path) = session_path {
+        if let Err(message) = session.save(&path) {
+            eprintln!("Couldn't save the session: {}", message);
+        }
+    }
+    if GRAPHICS_LOST.load(Ordering::Relaxed) {
+        std::process::exit(1);
+    }
+}
+
+/// Load settings from a JSON file, such as a sidecar written by `render_to_file`.
+fn load_settings(path: &Path) -> Result<MandelbrotSettings, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let json = json::Value::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    MandelbrotSettings::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Load a settings file given to `diff` or `montage`, with the environment and then the command line applied on
+/// top, the same as `--settings`.
+fn load_layered(path: &Path, options: &CliOptions) -> Result<MandelbrotSettings, String> {
+    let mut settings = load_settings(path)?;
+    cli::apply_env(&mut settings, |name| std::env::var(name).ok());
+    options.apply(&mut settings);
+    Ok(settings)
+}
+
+/// `diff a.json b.json [--output diff.png]`: render two settings files, print how much their iteration counts
+/// differ, and optionally save an image of where. Returns whether they were identical.
+fn run_diff(options: &CliOptions) -> Result<bool, String> {
+    let a = load_layered(&options.files[0], options)?;
+    let b = load_layered(&options.files[1], options)?;
+    a.check_size()?;
+    b.check_size()?;
+
+    let diff = CountDiff::new(&a, &b)?;
+    println!(
+        "max difference: {} | mean difference: {} | differing samples: {} of {}",
+        diff.max(),
+        diff.mean(),
+        diff.differing(),
+        diff.deltas.len()
+    );
+    if let Some(path) = &options.output {
+        diff.to_image()
+            .save(path)
+            .map_err(|e| format!("failed to save {}: {}", path.display(), e))?;
+    }
+    Ok(diff.differing() == 0)
+}
+
+/// `montage <view.json>... --output <sheet.png> [--columns N] [--cell WxH]`: render every settings file as a
+/// labelled thumbnail on one contact sheet. The columns default to a roughly square grid.
+fn run_montage(options: &CliOptions) -> Result<(), String> {
+    let (width, height) = options.cell;
+    let mut files = Vec::new();
+    for path in &options.files {
+        let view = load_layered(path, options)?;
+        MandelbrotSettings {
+            width,
+            height,
+            ..view.clone()
+        }
+        .check_size()?;
+        files.push(view);
+    }
+    let columns = (options.columns).unwrap_or((files.len() as f32).sqrt().ceil() as u32);
+    let output = options.output.as_ref().ok_or("montage needs --output")?;
+
+    let start = Instant::now();
+    let sheet = contact_sheet(&files, columns, width, height);
+    println!("Rendered {} in {:.1?}", output.display(), start.elapsed());
+    sheet
+        .save(output)
+        .map_err(|e| format!("failed to save {}: {}", output.display(), e))
+}
+
+/// Render the settings to an image file, optionally writing a JSON sidecar with the settings used.
+/// With a region, only that part of the view is rendered, and the sidecar describes the full view.
+fn render_to_file(
+    settings: &MandelbrotSettings,
+    path: &Path,
+    options: &CliOptions,
+    region: Option<Region>,
+) -> Result<(), String> {
+    let start = Instant::now();
+    let saved = if options.sixteen_bit {
+        let mut buffer = match region {
+            Some(region) => render::render_region_16(settings, region)?,
+            None => render::render_16(settings),
+        };
+        options.symmetry.apply(&mut buffer);
+        println!("Rendered {} in {:.1?}", path.display(), start.elapsed());
+        buffer.save(path)
+    } else {
+        let mut buffer = match region {
+            Some(region) => render::render_region(settings, region)?,
+            None => render::render(settings),
+        };
+        options.symmetry.apply(&mut buffer);
+        println!("Rendered {} in {:.1?}", path.display(), start.elapsed()); // Handy for comparing settings' speed
+        buffer.save(path)
+    };
+    saved.map_err(|e| format!("failed to save {}: {}", path.display(), e))?;
+
+    if options.sidecar {
+        let sidecar_path = path.with_extension("json");
+        std::fs::write(&sidecar_path, settings.to_json().to_pretty_string())
+            .map_err(|e| format!("failed to write {}: {}", sidecar_path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Render the view as `--tile-size` tiles, each saved as `tile_<column>_<row>.png` in `dir` as soon as it's done,
+/// so memory use is bounded by one tile however large the view. `manifest.json` lists every tile's file and pixel
+/// rectangle, along with the settings, for stitching them back together.
+fn render_tiles_to_dir(
+    settings: &MandelbrotSettings,
+    dir: &Path,
+    options: &CliOptions,
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    let size = options.tile_size;
+    let start = Instant::now();
+    let mut tiles = vec![];
+    for region in Region::tiles(settings, size) {
+        let file = format!("tile_{}_{}.png", region.x0 / size, region.y0 / size);
+        let path = dir.join(&file);
+        let saved = if options.sixteen_bit {
+            render::render_region_16(settings, region)?.save(&path)
+        } else {
+            render::render_region(settings, region)?.save(&path)
+        };
+        saved.map_err(|e| format!("failed to save {}: {}", path.display(), e))?;
+
+        let mut tile = BTreeMap::new();
+        tile.insert("file".to_string(), json::Value::String(file));
+        for (key, value) in [
+            ("x0", region.x0),
+            ("y0", region.y0),
+            ("x1", region.x1),
+            ("y1", region.y1),
+        ] {
+            tile.insert(key.to_string(), json::Value::Number(value as f64));
+        }
+        tiles.push(json::Value::Object(tile));
+    }
+    println!(
+        "Rendered {} tiles to {} in {:.1?}",
+        tiles.len(),
+        dir.display(),
+        start.elapsed()
+    );
+
+    let mut manifest = BTreeMap::new();
+    for (key, value) in [
+        ("width", settings.width),
+        ("height", settings.height),
+        ("tile_size", size),
+        ("columns", settings.width.div_ceil(size)),
+        ("rows", settings.height.div_ceil(size)),
+    ] {
+        manifest.insert(key.to_string(), json::Value::Number(value as f64));
+    }
+    manifest.insert("tiles".to_string(), json::Value::Array(tiles));
+    manifest.insert("settings".to_string(), settings.to_json());
+    let manifest_path = dir.join("manifest.json");
+    std::fs::write(
+        &manifest_path,
+        json::Value::Object(manifest).to_pretty_string(),
+    )
+    .map_err(|e| format!("failed to write {}: {}", manifest_path.display(), e))
+}
+
+/// Render `--frames` frames of a continuous zoom towards the centre into `--frames-dir`, as the viewer's `C` does
+/// with `--frames-dir`, stopping early at the precision limit. With `--tour`, fly through its waypoints instead.
+fn run_animate(mut settings: MandelbrotSettings, options: &CliOptions) -> Result<(), String> {
+    let dir = options
+        .frames_dir
+        .as_ref()
+        .ok_or("animate needs --frames-dir <dir>")?;
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+    if let Some(tour) = &options.tour {
+        return render_tour(&settings, &FlyPath::load(tour)?, dir, options);
+    }
+    let factor = CINEMATIC_ZOOM_RATE.powf(1. / EXPORT_FPS);
+    for frame_number in 0..options.frames.unwrap_or(ANIMATE_FRAMES) {
+        let path = dir.join(format!("frame_{:05}.png", frame_number));
+        render_to_file(&settings, &path, options, None)?;
+        if !settings.zoom_by(factor) {
+            println!(
+                "Stopping after {} frames: f32 precision limit reached",
+                frame_number + 1
+            );
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Render a fly-through of `tour` into `dir`, from its first waypoint to its last: `--frames` frames spread evenly
+/// along it, or without that as many as it takes at `EXPORT_FPS` to fly it at the pace of the viewer's tour.
+fn render_tour(
+    settings: &MandelbrotSettings,
+    tour: &FlyPath,
+    dir: &Path,
+    options: &CliOptions,
+) -> Result<(), String> {
+    if tour.waypoints.len() < 2 {
+        return Err("a tour needs at least 2 waypoints".to_string());
+    }
+    let frames = options
+        .frames
+        .unwrap_or_else(|| (tour_seconds(tour) * EXPORT_FPS).ceil() as u32 + 1);
+    for frame_number in 0..frames {
+        let progress = frame_number as f32 / (frames - 1).max(1) as f32;
+        let view = tour.view_at(settings, progress)?;
+        let path = dir.join(format!("frame_{:05}.png", frame_number));
+        render_to_file(&view, &path, options, None)?;
     }
+    Ok(())
 }
 
-/// Convert mouse position to mandelbrot coords.
-fn mouse_to_screen(mouse_pos: [f64; 2], settings: &MandelbrotSettings) -> [f32; 2] {
-    let [x, y] = mouse_pos;
-    [
-        (x as f32 - settings.width as f32 / 2.) / settings.width as f32 * 4.0 / settings.zoom
-            + settings.offset_x,
-        (y as f32 - settings.height as f32 / 2.) / settings.height as f32 * 4.0 / settings.zoom
-            + settings.offset_y,
-    ]
+/// How long the tour takes to fly at the auto-zoom's pace: an e-fold of zoom, or the matching pan, every
+/// 1 / ln(CINEMATIC_ZOOM_RATE) seconds. Never 0, so progress through it can always be divided out.
+fn tour_seconds(tour: &FlyPath) -> f32 {
+    let seconds = tour.length() / (CINEMATIC_ZOOM_RATE as f64).ln();
+    (seconds as f32).max(1. / EXPORT_FPS)
 }
 
-/// Convert an image to a texture for displaying.
+/// Print the tour's waypoints as `x y zoom`, numbered from 1 as the `remove` and `move` commands count them.
+fn print_tour(tour: &FlyPath) {
+    if tour.waypoints.is_empty() {
+        println!("The tour has no waypoints");
+    }
+    for (index, waypoint) in tour.waypoints.iter().enumerate() {
+        println!(
+            "{}: {} {} {}",
+            index + 1,
+            waypoint.offset_x,
+            waypoint.offset_y,
+            waypoint.zoom
+        );
+    }
+}
+
+/// Save the tour after an edit. A failure is reported but the edit is kept for this run.
+fn save_tour(tour: &FlyPath, path: &Path) {
+    if let Err(message) = tour.save(path) {
+        eprintln!("Couldn't save the tour: {}", message);
+    }
+}
+
+/// Render every view listed in `list` to a PNG in `--batch-dir`, returning how many failed. Each line is a JSON
+/// object or a query string such as `offset_x=-0.75&offset_y=0.1&zoom=20`, whose keys are the settings file's and
+/// override `settings`; an optional `name` key names the file, otherwise it's `view_<line>.png`. Blank lines and
+/// lines starting with `#` are skipped. A view that fails is reported and the rest still render.
+fn run_batch(
+    settings: &MandelbrotSettings,
+    list: &Path,
+    options: &CliOptions,
+) -> Result<usize, String> {
+    let text = std::fs::read_to_string(list)
+        .map_err(|e| format!("failed to read {}: {}", list.display(), e))?;
+    std::fs::create_dir_all(&options.batch_dir)
+        .map_err(|e| format!("failed to create {}: {}", options.batch_dir.display(), e))?;
+    let views: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let mut failed = 0;
+    for (done, &(number, line)) in views.iter().enumerate() {
+        println!("[{}/{}] line {}: {}", done + 1, views.len(), number, line);
+        let rendered = batch_view(settings, line).and_then(|(name, view)| {
+            let name = name.unwrap_or_else(|| format!("view_{}", number));
+            let path = options.batch_dir.join(format!("{}.png", name));
+            render_to_file(&view, &path, options, None)
+        });
+        if let Err(message) = rendered {
+            eprintln!("Line {} failed: {}", number, message);
+            failed += 1;
+        }
+    }
+    println!(
+        "Rendered {} of {} views, {} failed",
+        views.len() - failed,
+        views.len(),
+        failed
+    );
+    Ok(failed)
+}
+
+/// Parse one line of a batch file into the file name it gives, if any, and the settings of its view.
+fn batch_view(
+    settings: &MandelbrotSettings,
+    line: &str,
+) -> Result<(Option<String>, MandelbrotSettings), String> {
+    let overrides = if line.starts_with('{') {
+        json::Value::parse(line)?
+    } else {
+        // Query string values are numbers or booleans where they parse as them, otherwise strings
+        let mut map = BTreeMap::new();
+        for pair in line.split('&') {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", pair))?;
+            let value = match (value.parse::<f64>(), value) {
+                (Ok(number), _) => json::Value::Number(number),
+                (_, "true" | "false") => json::Value::Bool(value == "true"),
+                _ => json::Value::String(value.to_string()),
+            };
+            map.insert(key.to_string(), value);
+        }
+        json::Value::Object(map)
+    };
+    let json::Value::Object(mut overrides) = overrides else {
+        return Err("expected a JSON object".to_string());
+    };
+
+    let name = match overrides.remove("name") {
+        Some(json::Value::String(name)) if !name.is_empty() && !name.contains(['/', '\\']) => {
+            Some(name)
+        }
+        Some(_) => return Err("'name' must be a file name without directories".to_string()),
+        None => None,
+    };
+    let json::Value::Object(mut merged) = settings.to_json() else {
+        unreachable!("settings are always written as an object")
+    };
+    merged.extend(overrides);
+    let view = MandelbrotSettings::from_json(&json::Value::Object(merged))?;
+    view.check_size()?;
+    Ok((name, view))
+}
+
+/// Text describing the current view, shown in the window title.
+/// Depth is the magnification relative to `initial_zoom` as a power of ten, which is how deep zooms are usually shared.
+fn status_text(settings: &MandelbrotSettings, initial_zoom: f32, use_preview: bool) -> String {
+    format!(
+        "Mandelbrot! | centre: {}, {} | zoom: {} | depth: 10^{:.2} | zoom step: {:.1} | iterations: {}{}{}{}{}{}",
+        format_coord(settings.offset_x, settings.zoom),
+        format_coord(settings.offset_y, settings.zoom),
+        settings.zoom,
+        (settings.zoom / initial_zoom).log10(),
+        settings.zoom_exp,
+        settings.max_iterations,
+        match settings.fractal {
+            FractalKind::Julia => format!(" | c: {}, {}", settings.julia_c.re, settings.julia_c.im),
+            _ => String::new(),
+        },
+        if use_preview { "" } else { " | previews off" },
+        match settings.blend_palette {
+            Some(_) => format!(" | palette blend: {:.1}", settings.palette_blend),
+            None => String::new(),
+        },
+        match settings.colour_offset {
+            0. => String::new(),
+            offset => format!(" | colour offset: {:.2}", offset),
+        },
+        if settings.is_resolvable(settings.zoom * settings.zoom_exp) {
+            ""
+        } else {
+            " | precision limit reached"
+        }
+    )
+}
+
+/// How much of the view an area estimate puts in the set, and the area that is in the flat view. With
+/// `random`, the estimate came from random points and its standard error is given too.
+fn area_text(estimate: &AreaEstimate, settings: &MandelbrotSettings, random: bool) -> String {
+    let (Some(fraction), Some(error)) = (estimate.fraction(), estimate.standard_error()) else {
+        return "set: no samples to count".to_string();
+    };
+    let mut text = format!("set: {:.2}%", fraction * 100.);
+    if random {
+        text += &format!(" ± {:.2}%", error * 100.);
+    }
+    text += " of the view";
+    if let Some(area) = estimate.area(settings) {
+        text += &format!(", area {:.4}", area);
+        if random {
+            let view = area / fraction.max(f64::MIN_POSITIVE); // Scaling the error the same way
+            text += &format!(" ± {:.4}", error * view);
+        }
+    }
+    if random {
+        text += &format!(" from {} random points", estimate.samples);
+    }
+    text
+}
+
+/// Title text for the measurement between up to two clicked points: a prompt for the next point, or the
+/// distance in the plane and, in the flat view, on screen.
+fn measure_text(settings: &MandelbrotSettings, points: &[Complex<f32>]) -> String {
+    match points {
+        [] => " | measuring, click the first point".to_string(),
+        [_] => " | measuring, click the second point".to_string(),
+        [a, b, ..] => {
+            let mut text = format!(" | distance: {}", (b - a).norm());
+            if settings.view_transform == ViewTransform::Linear {
+                // Measured from where the points are now, so it stays right after zooming or panning
+                let [ax, ay] = render::complex_to_pixel(settings, *a);
+                let [bx, by] = render::complex_to_pixel(settings, *b);
+                text += &format!(" ({:.1} px)", (bx - ax).hypot(by - ay));
+            }
+            text
+        }
+    }
+}
+
+/// Characters that can appear in a typed go to target.
+fn is_goto_char(c: char) -> bool {
+    c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | ',' | ' ' | 'e' | 'E')
+}
+
+/// Format a coordinate with enough decimal places to tell neighbouring views apart at the given zoom.
+/// At zoom 1 three places is about a pixel's width, and each power of ten of zoom needs one more.
+fn format_coord(value: f32, zoom: f32) -> String {
+    let extra_digits = if zoom.is_finite() && zoom > 0. {
+        zoom.log10().ceil()
+    } else {
+        0.
+    };
+    // An f32 only holds about 9 significant digits, enough to round-trip it, so places past those are noise.
+    // How many places that leaves depends on how many digits come before them
+    let held = match value.abs() {
+        magnitude if magnitude > 0. && magnitude.is_finite() => 8. - magnitude.log10().floor(),
+        _ => f32::INFINITY, // 0 has no digits to run out of
+    };
+    let precision = (3. + extra_digits).min(held).clamp(1., 45.) as usize; // 45 places reach the smallest f32
+    format!("{:.*}", precision, value)
+}
+
+/// Move the view so its content follows a drag of `moved` pixels, which needn't be whole.
+fn drag_view(settings: &mut MandelbrotSettings, moved: [f64; 2]) {
+    let [width_scale, height_scale] = settings.pixel_scale();
+    settings.offset_x -= moved[0] as f32 * width_scale;
+    settings.offset_y -= moved[1] as f32 * height_scale;
+}
+
+/// Convert mouse position to mandelbrot coords. With `snap`, gives the coordinate iterated for the pixel under the
+/// cursor instead, so clicking the centre pixel leaves the centre exactly where it was.
+fn mouse_to_screen(mouse_pos: [f64; 2], settings: &MandelbrotSettings, snap: bool) -> [f32; 2] {
+    let [x, y] = if snap {
+        mouse_pos.map(f64::floor)
+    } else {
+        mouse_pos
+    };
+    let c = render::pixel_to_complex(settings, x as f32, y as f32);
+    [c.re, c.im]
+}
+
+/// An image showing in the window, with the pixels it was uploaded from, to upload it again if it has to be.
+struct Shown {
+    texture: Texture<gfx_device_gl::Resources>,
+    buffer: ImageBuffer<Rgba<u8>, Vec<u8>>,
+}
+
+/// Convert an image to a texture for displaying, adding the time the upload took to `upload_time`.
+/// A failed upload, as after the GL context is lost on some systems over sleep and resume, is tried once more in
+/// a new texture context, and the images already showing are then uploaded again too (see `CONTEXT_REPLACED`).
+/// If that fails as well the window can't show anything, so it's reported and the window closes, through the
+/// usual shutdown so the session is still saved.
 fn unwrap_image_to_texture(
     img: ImageBuffer<Rgba<u8>, Vec<u8>>,
     window: &mut PistonWindow,
-) -> Texture<gfx_device_gl::Resources> {
-    Texture::from_image(
+    upload_time: &mut Duration,
+) -> Option<Shown> {
+    let start = Instant::now();
+    let mut upload = || {
+        Texture::from_image(
+            &mut window.create_texture_context(),
+            &img,
+            &TextureSettings::new(),
+        )
+    };
+    let texture = upload().or_else(|e| {
+        eprintln!(
+            "Failed to upload the image ({:?}), retrying in a new texture context",
+            e
+        );
+        let retried = upload();
+        CONTEXT_REPLACED.store(retried.is_ok(), Ordering::Relaxed);
+        retried
+    });
+    *upload_time += start.elapsed();
+    match texture {
+        Ok(texture) => Some(Shown {
+            texture,
+            buffer: img,
+        }),
+        Err(e) => {
+            eprintln!("Failed to upload the image ({:?}), closing the viewer", e);
+            GRAPHICS_LOST.store(true, Ordering::Relaxed);
+            window.set_should_close(true);
+            None
+        }
+    }
+}
+
+/// Upload an image that's showing again, in a new texture context. Returns false, closing the window the same
+/// way as `unwrap_image_to_texture`, if it fails.
+fn reupload(shown: &mut Shown, window: &mut PistonWindow) -> bool {
+    let texture = Texture::from_image(
         &mut window.create_texture_context(),
-        &img,
+        &shown.buffer,
         &TextureSettings::new(),
-    )
-    .unwrap()
-}
-
-/// Generate a mandelbrot image given settings.
-fn generate_mandelbrot_buffer(settings: &MandelbrotSettings) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let mut img = ImageBuffer::new(settings.width, settings.height);
-    let columns = img.width() as usize;
-
-    // Cache some values to avoid recalculation
-    let width_64 = settings.width as f32;
-    let height_64 = settings.height as f32;
-    let width_scale = 4. / settings.zoom / width_64;
-    let height_scale = 4. / settings.zoom / height_64;
-    let half_width = width_64 / 2.;
-    let half_height = height_64 / 2.;
-
-    // Iterate over the image in parallel
-    img.as_mut()
-        .par_chunks_mut(columns * 4) // Split the image into rows. *4 is used because each pixel has 4 channels
-        .enumerate() // Enumerate the rows in parallel
-        .for_each(|(y, row)| {
-            let yi = (y as f32 - half_height) * height_scale + settings.offset_y; // Y coord
-            for (x, pixel) in row.chunks_mut(4).enumerate() {
-                let xi = (x as f32 - half_width) * width_scale + settings.offset_x; // X coord
-
-                // Iterate the mandelbrot function: z = z^2 + c
-                let c = Complex::<f32>::new(xi, yi);
-                let mut z = Complex::<f32>::new(xi, yi);
-                let mut i = 0;
-                while i < settings.max_iterations && z.norm_sqr() <= 4. {
-                    z = z * z + c;
-                    i += 1;
-                }
-
-                let lum =
-                    ((i as f32 / settings.max_iterations as f32).powf(settings.gamma) * 255.0) as u8; // scale final value and correct gamma
-                pixel.copy_from_slice(&[lum, lum, lum, 255]); // set pixel colour
-            }
-        });
+    );
+    match texture {
+        Ok(texture) => {
+            shown.texture = texture;
+            true
+        }
+        Err(e) => {
+            eprintln!(
+                "Failed to upload the image again ({:?}), closing the viewer",
+                e
+            );
+            GRAPHICS_LOST.store(true, Ordering::Relaxed);
+            window.set_should_close(true);
+            false
+        }
+    }
+}
 
-    img
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deeper_zooms_show_more_digits_up_to_what_f32_holds() {
+        let digits = |zoom| format_coord(-0.7436439, zoom).len();
+        assert!(digits(1.) < digits(1e2));
+        assert!(digits(1e2) < digits(1e4));
+        // "-0." and 9 significant digits, however deep
+        assert_eq!(format_coord(-0.7436439, 1e6), "-0.743643880");
+        assert_eq!(digits(1e12), digits(1e6));
+        assert_eq!(format_coord(1234.5, 1e9), "1234.50000");
+        assert_eq!(format_coord(0., 1e6), "0.000000000");
+    }
+
+    #[test]
+    fn snapped_clicks_on_the_centre_pixel_leave_the_centre_alone() {
+        let mut settings = MandelbrotSettings {
+            width: 800,
+            height: 600,
+            offset_x: -0.7436439,
+            offset_y: 0.1318259,
+            ..MandelbrotSettings::default()
+        };
+        // Recentre on some pixel, then keep clicking anywhere within the centre pixel, as a hand would land
+        [settings.offset_x, settings.offset_y] = mouse_to_screen([123.6, 77.2], &settings, true);
+        let cursor = [400.37, 300.81];
+        let snapped = mouse_to_screen(cursor, &settings, true);
+        for _ in 0..5 {
+            [settings.offset_x, settings.offset_y] = mouse_to_screen(cursor, &settings, true);
+            assert_eq!([settings.offset_x, settings.offset_y], snapped);
+            let zoom_exp = settings.zoom_exp;
+            settings.zoom_by(zoom_exp);
+        }
+        // Without snapping each click drifts by the cursor's offset within the pixel
+        let unsnapped = mouse_to_screen(cursor, &settings, false);
+        assert_ne!(unsnapped, snapped);
+    }
 }
@@ -1,12 +1,968 @@
+use crate::fractal::FractalKind;
+use crate::importance::ImportanceMap;
+use crate::json::Value;
+use crate::palette::Palette;
+use crate::render::{
+    pixel_to_complex, ColourMode, DownscaleFilter, EscapeShape, InteriorMode, Precision, Region,
+    SamplePattern, Transparency, ViewTransform, MAX_SMOOTH_EXTRA_ITERATIONS,
+};
+use num_complex::Complex;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Smallest allowed zoom step. Anything <= 1 would stop zoom-in from magnifying.
+pub const MIN_ZOOM_EXP: f32 = 1.1;
+/// Largest allowed zoom step, to stop a single click from jumping past all detail.
+pub const MAX_ZOOM_EXP: f32 = 10.0;
+/// Most samples a single render may iterate, including supersampling. Each takes 8 bytes between the
+/// counts and the image, so this caps a render at about 1 GiB rather than letting a typo exhaust memory.
+pub const MAX_RENDER_SAMPLES: u64 = 1 << 27;
+/// Width and height of the complex plane shown at zoom 1, whatever the view's size in pixels.
+pub const WORLD_SPAN: f32 = 4.0;
+/// Smallest bailout radius. Any point of the Mandelbrot set stays within 2, so less would escape points of the set.
+pub const MIN_BAILOUT: f32 = 2.;
+/// Largest bailout radius. Its square, and the step past it, stay within f32.
+pub const MAX_BAILOUT: f32 = 1e6;
+/// How many f32 steps apart, around the centre, neighbouring pixels have to be for a view to be iterated in f32.
+/// Closer than this the rounding shows, so deeper views are iterated in f64 (see `MandelbrotSettings::needs_f64`).
+const F32_STEPS_PER_PIXEL: f32 = 16.;
+/// How many f32 steps wide a view iterated in f64 has to stay. The centre is still an f32, so much deeper than
+/// this a click or pan could only move it by a good part of the view.
+const F32_STEPS_PER_VIEW: f32 = 64.;
+/// Default colour for pixels whose coordinates or iteration became NaN/inf. Magenta doesn't appear in the grayscale ramp.
+pub const DEGENERATE_COLOUR: [u8; 4] = [255, 0, 255, 255];
 
 /// Settings specifying how to render a region of the Mandelbrot.
+#[derive(Clone)]
 pub struct MandelbrotSettings {
+    pub fractal: FractalKind,
     pub width: u32,
     pub height: u32,
     pub max_iterations: u32,
+    pub navigation_iterations: Option<u32>, // Iteration cap for previews while navigating. `None` scales it with the resolution
+    pub importance: Option<Arc<ImportanceMap>>, // Share of max_iterations each part of the view gets. `None` gives all of it everywhere
+    // Value z starts from, 0 in the standard definition. `None` starts at c itself, as renders before this
+    // was configurable did. That's one iteration ahead, so every escaping point's count is one lower
+    pub z0: Option<Complex<f32>>,
+    pub julia_c: Complex<f32>, // The constant c of the Julia set, when `fractal` is `Julia`. z0 doesn't apply
     pub zoom: f32,
     pub zoom_exp: f32,
+    pub view_transform: ViewTransform, // How pixels map onto the plane around the offset
+    pub y_stretch: f32, // Extra zoom of the imaginary axis only, for stretched views. 1 zooms both axes alike
     pub offset_x: f32,
     pub offset_y: f32,
     pub gamma: f32,
-}
\ No newline at end of file
+    pub exterior_lift: f32, // Brighten (positive) or darken (negative) the first few escaped bands, from -1 to 1
+    pub escape_shape: EscapeShape, // Region a point must leave to escape. The diamond is cheapest, but changes the band shapes
+    pub bailout: f32, // Radius of the circle the escape shape just contains. At least 2, so no point escapes too early
+    pub supersample: u32, // Render this many times larger in each direction, then shrink. 1 disables it
+    pub downscale_filter: DownscaleFilter, // How supersampled renders are shrunk
+    pub sample_pattern: SamplePattern, // Where within each pixel supersampled renders take their samples
+    pub sample_seed: u32, // Seed for the random sample patterns, so renders with the same seed match exactly
+    pub precision: Precision, // Whether points are iterated in f32, f64, or f64 only where it matters
+    pub f64_region: Option<Region>, // Pixels `Precision::Hybrid` always iterates in f64, besides the boundary
+    pub contours: bool,             // Darken the boundaries between iteration bands
+    pub edge_blend: bool, // Soften the set's outline by pulling late-escaping pixels towards the interior's colour
+    pub colour_mode: ColourMode, // Whole or fractional iteration counts
+    pub smooth_extra_iterations: u32, // Steps past the bailout averaged into smooth counts, up to 4, to reduce shimmer
+    pub log_colour: bool, // Index the ramp by log(1 + count), spreading the high counts of deep zooms over more colours
+    pub colour_period: Option<f32>, // Iterations per repeat of the colour ramp. `None` spans it over max_iterations
+    pub colour_offset: f32, // Fraction of the ramp the exterior's colours are rotated by, wrapping from the end to the start
+    pub palette: Option<Arc<Palette>>, // Colour ramp to use instead of grayscale. Arc so it's shared between clones and threads
+    pub blend_palette: Option<Arc<Palette>>, // Second ramp mixed into the first (or grayscale) by `palette_blend`
+    pub palette_blend: f32, // 0 is purely the first colouring, 1 purely `blend_palette`
+    pub interior_mode: InteriorMode,
+    pub interior_colour: Option<[u8; 4]>, // Colour for points in the set. `None` uses the end of the ramp
+    pub degenerate_colour: [u8; 4], // Colour for pixels whose count is NaN, making breakdowns obvious
+    pub transparency: Transparency, // Part of the image given no alpha, for compositing. Degenerate pixels stay opaque
+}
+
+impl Default for MandelbrotSettings {
+    /// The whole set in a 640x480 view.
+    fn default() -> MandelbrotSettings {
+        MandelbrotSettings {
+            fractal: FractalKind::Mandelbrot,
+            width: 640,
+            height: 480,
+            max_iterations: 300,
+            navigation_iterations: None,
+            importance: None,
+            z0: Some(Complex::new(0., 0.)),
+            julia_c: Complex::new(-0.4, 0.6),
+            zoom: 1.,
+            zoom_exp: 1.5,
+            view_transform: ViewTransform::Linear,
+            y_stretch: 1.,
+            offset_x: 0.0,
+            offset_y: 0.0,
+            gamma: 0.22,
+            exterior_lift: 0.,
+            escape_shape: EscapeShape::Circle,
+            bailout: 2.,
+            supersample: 1,
+            downscale_filter: DownscaleFilter::Lanczos3,
+            sample_pattern: SamplePattern::Rotated,
+            sample_seed: 0,
+            precision: Precision::Auto,
+            f64_region: None,
+            contours: false,
+            edge_blend: false,
+            colour_mode: ColourMode::Bands,
+            smooth_extra_iterations: 2,
+            log_colour: false,
+            colour_period: None,
+            colour_offset: 0.,
+            palette: None,
+            blend_palette: None,
+            palette_blend: 0.5,
+            interior_mode: InteriorMode::Flat,
+            interior_colour: None,
+            degenerate_colour: DEGENERATE_COLOUR,
+            transparency: Transparency::Opaque,
+        }
+    }
+}
+
+impl MandelbrotSettings {
+    /// Set the zoom step, clamping it so zooming in always magnifies.
+    pub fn set_zoom_exp(&mut self, zoom_exp: f32) {
+        self.zoom_exp = if zoom_exp.is_finite() {
+            zoom_exp.clamp(MIN_ZOOM_EXP, MAX_ZOOM_EXP)
+        } else {
+            MIN_ZOOM_EXP // NaN/inf would break every later zoom, so fall back to the smallest step
+        };
+    }
+}
+
+impl MandelbrotSettings {
+    /// Distance between neighbouring pixels' coordinates along the real and imaginary axes.
+    pub fn pixel_scale(&self) -> [f32; 2] {
+        [
+            self.world_width() / self.width as f32,
+            self.world_height() / self.height as f32,
+        ]
+    }
+
+    /// Span of the real axis currently visible. `WORLD_SPAN` at zoom 1.
+    pub fn world_width(&self) -> f32 {
+        WORLD_SPAN / self.zoom
+    }
+
+    /// Span of the imaginary axis currently visible, narrower than the width when `y_stretch` magnifies it.
+    pub fn world_height(&self) -> f32 {
+        WORLD_SPAN / (self.zoom * self.y_stretch)
+    }
+
+    /// The pixel to complex mapping as a 2x3 affine matrix, for handing the view to other tools: pixel (x, y)
+    /// maps to `re = m[0][0] x + m[0][1] y + m[0][2]` and `im = m[1][0] x + m[1][1] y + m[1][2]`, matching
+    /// `pixel_to_complex`. There's no rotation, so the off-diagonal terms are 0. `None` for the exponential
+    /// and sphere views, which aren't affine.
+    pub fn view_matrix(&self) -> Option<[[f32; 3]; 2]> {
+        if self.view_transform != ViewTransform::Linear {
+            return None;
+        }
+        let [width_scale, height_scale] = self.pixel_scale();
+        Some([
+            [
+                width_scale,
+                0.,
+                self.offset_x - self.width as f32 / 2. * width_scale,
+            ],
+            [
+                0.,
+                height_scale,
+                self.offset_y - self.height as f32 / 2. * height_scale,
+            ],
+        ])
+    }
+
+    /// Coordinates of the image's corners, clockwise from the top left: the outer corners of the corner pixels,
+    /// so in the flat view they're `world_width()` apart along the real axis and `world_height()` along the
+    /// imaginary, centred on the offset. Other views give wherever they put those corners, as `pixel_to_complex`.
+    pub fn corners(&self) -> [Complex<f32>; 4] {
+        let [width, height] = [self.width as f32, self.height as f32];
+        [[0., 0.], [width, 0.], [width, height], [0., height]]
+            .map(|[x, y]| pixel_to_complex(self, x, y))
+    }
+
+    /// Zoom so `width` of the real axis is visible, keeping the centre. Fails, leaving the zoom unchanged,
+    /// if the width isn't positive and finite or is too narrow to resolve.
+    pub fn set_world_width(&mut self, width: f32) -> Result<(), String> {
+        let zoom = WORLD_SPAN / width;
+        if !(width > 0. && zoom.is_finite()) {
+            return Err(format!("invalid world width {}", width));
+        }
+        if !self.is_resolvable(zoom) {
+            return Err("world width is past the precision limit".to_string());
+        }
+        self.zoom = zoom;
+        Ok(())
+    }
+
+    /// Whether neighbouring pixels still map to different coordinates at `zoom`. Past this the pixel step rounds
+    /// away (or underflows to 0) and the whole image goes flat, so zooming further shows nothing new.
+    ///
+    /// Linear views too deep for f32 are iterated in f64 (see `needs_f64`), so for them the limit is instead
+    /// how finely the f32 centre can be placed: the view has to stay `F32_STEPS_PER_VIEW` f32 steps wide, so
+    /// clicks and pans can still put the centre close to where they aim.
+    pub fn is_resolvable(&self, zoom: f32) -> bool {
+        let (pixel_step, f32_step) = MandelbrotSettings {
+            zoom,
+            ..self.clone()
+        }
+        .precision();
+        let view_width = pixel_step * self.width.min(self.height) as f32;
+        pixel_step.is_normal()
+            && (pixel_step > f32_step
+                || self.view_transform == ViewTransform::Linear
+                    && view_width > f32_step * F32_STEPS_PER_VIEW)
+    }
+
+    /// Whether the view is too deep to iterate in f32. Neighbouring pixels are then so few f32 steps apart that
+    /// their coordinates round together in blocks and their orbits drift apart into noise, so the renderer works
+    /// out the coordinates and iterates them in f64 instead. Only the linear view goes this deep.
+    pub fn needs_f64(&self) -> bool {
+        let (pixel_step, f32_step) = self.precision();
+        self.view_transform == ViewTransform::Linear && pixel_step < f32_step * F32_STEPS_PER_PIXEL
+    }
+
+    /// The smaller of the pixel steps, and how far apart neighbouring f32 values are around the centre.
+    fn precision(&self) -> (f32, f32) {
+        let [width_scale, height_scale] = self.pixel_scale();
+        let magnitude = self.offset_x.abs().max(self.offset_y.abs());
+        (width_scale.min(height_scale), magnitude * f32::EPSILON)
+    }
+
+    /// Multiply the zoom by `factor`, unless that would zoom past the precision limit.
+    /// Returns whether the zoom changed.
+    pub fn zoom_by(&mut self, factor: f32) -> bool {
+        let zoom = self.zoom * factor;
+        if zoom > 0. && self.is_resolvable(zoom) {
+            self.zoom = zoom;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Zoom by `factor` keeping the coordinate `target` at pixel (x, y) of a flat view. The offset is worked out
+    /// afresh from the target and the new zoom rather than nudged, so repeated small zooms, such as the frames of
+    /// an animation, can't let the target drift. Nothing changes if the new zoom is past the precision limit.
+    pub fn zoom_about(&mut self, target: Complex<f32>, [x, y]: [f32; 2], factor: f32) -> bool {
+        if !self.zoom_by(factor) {
+            return false;
+        }
+        // Invert the pixel to coordinate mapping of `pixel_to_complex`
+        let [width_scale, height_scale] = self.pixel_scale();
+        self.offset_x = target.re - (x - self.width as f32 / 2.) * width_scale;
+        self.offset_y = target.im - (y - self.height as f32 / 2.) * height_scale;
+        true
+    }
+
+    /// Check the render size is something that can actually be rendered: not empty, and not so large
+    /// (after supersampling) that the buffers wouldn't fit in memory.
+    pub fn check_size(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!(
+                "can't render a {}x{} image, width and height must be at least 1",
+                self.width, self.height
+            ));
+        }
+        // In u128, which even the largest u32 sizes can't overflow
+        let samples = self.width as u128 * self.height as u128 * (self.supersample as u128).pow(2);
+        if samples > MAX_RENDER_SAMPLES as u128 {
+            return Err(format!(
+                "{}x{} with {}x supersampling is {} samples, more than the limit of {}",
+                self.width, self.height, self.supersample, samples, MAX_RENDER_SAMPLES
+            ));
+        }
+        Ok(())
+    }
+
+    /// Move the view by whole pixels, so the pixels still in view can be reused.
+    pub fn pan_pixels(&mut self, dx: i32, dy: i32) {
+        let [width_scale, height_scale] = self.pixel_scale();
+        self.offset_x += dx as f32 * width_scale;
+        self.offset_y += dy as f32 * height_scale;
+    }
+
+    /// Jump to a view typed as `x y` or `x y zoom`, separated by spaces or commas.
+    /// Nothing is changed if the text is malformed, so the previous view is kept.
+    pub fn go_to(&mut self, text: &str) -> Result<(), String> {
+        let numbers = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                part.parse::<f32>()
+                    .map_err(|_| format!("'{}' isn't a number", part))
+            })
+            .collect::<Result<Vec<f32>, String>>()?;
+
+        let (x, y, zoom) = match numbers[..] {
+            [x, y] => (x, y, self.zoom),
+            [x, y, zoom] => (x, y, zoom),
+            _ => return Err("expected 'x y' or 'x y zoom'".to_string()),
+        };
+        self.set_view(x, y, zoom)
+    }
+
+    /// Centre the view on `x`, `y` at `zoom`. Nothing is changed if the view would be invalid or past the
+    /// precision limit.
+    pub fn set_view(&mut self, x: f32, y: f32, zoom: f32) -> Result<(), String> {
+        if !(x.is_finite() && y.is_finite() && zoom.is_finite() && zoom > 0.) {
+            return Err("coordinates must be finite and zoom positive".to_string());
+        }
+
+        let previous = (self.offset_x, self.offset_y);
+        (self.offset_x, self.offset_y) = (x, y);
+        if !self.is_resolvable(zoom) {
+            (self.offset_x, self.offset_y) = previous;
+            return Err("zoom is past the precision limit for that coordinate".to_string());
+        }
+        self.zoom = zoom;
+        Ok(())
+    }
+
+    /// Cheaper settings for the same view, with the resolution divided by `scale`. The iterations are capped at
+    /// `navigation_iterations` if set, otherwise divided by `scale` too.
+    pub fn preview(&self, scale: u32) -> MandelbrotSettings {
+        let iterations = match self.navigation_iterations {
+            Some(cap) => cap.min(self.max_iterations),
+            None => self.max_iterations / scale,
+        };
+        MandelbrotSettings {
+            width: (self.width / scale).max(1),
+            height: (self.height / scale).max(1),
+            max_iterations: iterations.max(1),
+            supersample: 1,
+            ..self.clone()
+        }
+    }
+
+    /// The same view at the resolution actually iterated when supersampling.
+    pub fn supersampled(&self) -> MandelbrotSettings {
+        MandelbrotSettings {
+            width: self.width * self.supersample,
+            height: self.height * self.supersample,
+            supersample: 1,
+            ..self.clone()
+        }
+    }
+
+    /// The same view shifted by a fraction of a pixel, for sampling different points within each pixel.
+    pub fn jittered(&self, [dx, dy]: [f32; 2]) -> MandelbrotSettings {
+        let [width_scale, height_scale] = self.pixel_scale();
+        MandelbrotSettings {
+            offset_x: self.offset_x + dx * width_scale,
+            offset_y: self.offset_y + dy * height_scale,
+            ..self.clone()
+        }
+    }
+
+    /// Describe the settings as JSON, so a render can be reproduced later.
+    pub fn to_json(&self) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert(
+            "fractal".to_string(),
+            Value::String(self.fractal.name().to_string()),
+        );
+        map.insert("width".to_string(), Value::Number(self.width as f64));
+        map.insert("height".to_string(), Value::Number(self.height as f64));
+        map.insert(
+            "max_iterations".to_string(),
+            Value::Number(self.max_iterations as f64),
+        );
+        if let Some(iterations) = self.navigation_iterations {
+            map.insert(
+                "navigation_iterations".to_string(),
+                Value::Number(iterations as f64),
+            );
+        }
+        if let Some(z0) = self.z0 {
+            map.insert(
+                "z0".to_string(),
+                Value::Array(vec![Value::from_f32(z0.re), Value::from_f32(z0.im)]),
+            );
+        }
+        map.insert(
+            "julia_c".to_string(),
+            Value::Array(vec![
+                Value::from_f32(self.julia_c.re),
+                Value::from_f32(self.julia_c.im),
+            ]),
+        );
+        map.insert("zoom".to_string(), Value::from_f32(self.zoom));
+        map.insert("zoom_exp".to_string(), Value::from_f32(self.zoom_exp));
+        map.insert(
+            "view_transform".to_string(),
+            Value::String(self.view_transform.name().to_string()),
+        );
+        map.insert("y_stretch".to_string(), Value::from_f32(self.y_stretch));
+        map.insert("offset_x".to_string(), Value::from_f32(self.offset_x));
+        map.insert("offset_y".to_string(), Value::from_f32(self.offset_y));
+        map.insert("gamma".to_string(), Value::from_f32(self.gamma));
+        map.insert(
+            "exterior_lift".to_string(),
+            Value::from_f32(self.exterior_lift),
+        );
+        map.insert(
+            "escape_shape".to_string(),
+            Value::String(self.escape_shape.name().to_string()),
+        );
+        map.insert("bailout".to_string(), Value::from_f32(self.bailout));
+        map.insert("contours".to_string(), Value::Bool(self.contours));
+        map.insert("edge_blend".to_string(), Value::Bool(self.edge_blend));
+        map.insert(
+            "colour_mode".to_string(),
+            Value::String(self.colour_mode.name().to_string()),
+        );
+        map.insert(
+            "smooth_extra_iterations".to_string(),
+            Value::Number(self.smooth_extra_iterations as f64),
+        );
+        map.insert("log_colour".to_string(), Value::Bool(self.log_colour));
+        if let Some(period) = self.colour_period {
+            map.insert("colour_period".to_string(), Value::from_f32(period));
+        }
+        map.insert(
+            "colour_offset".to_string(),
+            Value::from_f32(self.colour_offset),
+        );
+        map.insert(
+            "supersample".to_string(),
+            Value::Number(self.supersample as f64),
+        );
+        map.insert(
+            "downscale_filter".to_string(),
+            Value::String(self.downscale_filter.name().to_string()),
+        );
+        map.insert(
+            "sample_pattern".to_string(),
+            Value::String(self.sample_pattern.name().to_string()),
+        );
+        map.insert(
+            "sample_seed".to_string(),
+            Value::Number(self.sample_seed as f64),
+        );
+        map.insert(
+            "precision".to_string(),
+            Value::String(self.precision.name().to_string()),
+        );
+        if let Some(region) = self.f64_region {
+            let corners = [region.x0, region.y0, region.x1, region.y1];
+            map.insert(
+                "f64_region".to_string(),
+                Value::Array(corners.map(|n| Value::Number(n as f64)).to_vec()),
+            );
+        }
+        if let Some(source) = self.importance.as_ref().and_then(|m| m.source.as_ref()) {
+            map.insert(
+                "importance".to_string(),
+                Value::String(source.display().to_string()),
+            );
+        }
+        if let Some(source) = self.palette.as_ref().and_then(|p| p.source.as_ref()) {
+            map.insert(
+                "palette".to_string(),
+                Value::String(source.display().to_string()),
+            );
+        }
+        if let Some(source) = self.blend_palette.as_ref().and_then(|p| p.source.as_ref()) {
+            map.insert(
+                "blend_palette".to_string(),
+                Value::String(source.display().to_string()),
+            );
+        }
+        map.insert(
+            "palette_blend".to_string(),
+            Value::from_f32(self.palette_blend),
+        );
+        map.insert(
+            "interior_mode".to_string(),
+            Value::String(self.interior_mode.name().to_string()),
+        );
+        map.insert(
+            "transparency".to_string(),
+            Value::String(self.transparency.name().to_string()),
+        );
+        if let Some(colour) = self.interior_colour {
+            map.insert("interior_colour".to_string(), colour_to_json(colour));
+        }
+        map.insert(
+            "degenerate_colour".to_string(),
+            colour_to_json(self.degenerate_colour),
+        );
+        Value::Object(map)
+    }
+
+    /// Read settings back from the JSON written by `to_json`.
+    pub fn from_json(json: &Value) -> Result<MandelbrotSettings, String> {
+        let number = |key: &str| {
+            json.get(key)
+                .and_then(Value::as_f64)
+                .ok_or_else(|| format!("missing or non-numeric '{}'", key))
+        };
+        // Flags added after the first sidecars were written default to off when missing
+        let flag = |key: &str| json.get(key).and_then(Value::as_bool).unwrap_or(false);
+        let fractal = match json.get("fractal").and_then(Value::as_str) {
+            Some(name) => {
+                FractalKind::from_name(name).ok_or_else(|| format!("unknown fractal '{}'", name))?
+            }
+            None => FractalKind::Mandelbrot,
+        };
+        Ok(MandelbrotSettings {
+            fractal,
+            width: number("width")? as u32,
+            height: number("height")? as u32,
+            max_iterations: match number("max_iterations")? as u32 {
+                0 => return Err("'max_iterations' must be at least 1".to_string()),
+                iterations => iterations,
+            },
+            navigation_iterations: json
+                .get("navigation_iterations")
+                .and_then(Value::as_f64)
+                .map(|n| (n as u32).max(1)),
+            // Missing means z started at c, as every render did before z0 could be set
+            z0: match json.get("z0").and_then(Value::as_array) {
+                Some([re, im]) => match (re.as_f64(), im.as_f64()) {
+                    (Some(re), Some(im)) => Some(Complex::new(re as f32, im as f32)),
+                    _ => return Err("non-numeric 'z0'".to_string()),
+                },
+                Some(_) => return Err("'z0' must be [re, im]".to_string()),
+                None => None,
+            },
+            julia_c: match json.get("julia_c").and_then(Value::as_array) {
+                Some([re, im]) => match (re.as_f64(), im.as_f64()) {
+                    (Some(re), Some(im)) => Complex::new(re as f32, im as f32),
+                    _ => return Err("non-numeric 'julia_c'".to_string()),
+                },
+                Some(_) => return Err("'julia_c' must be [re, im]".to_string()),
+                None => Complex::new(-0.4, 0.6),
+            },
+            zoom: match number("zoom")? as f32 {
+                zoom if zoom.is_finite() && zoom > 0. => zoom,
+                _ => return Err("'zoom' must be a positive number".to_string()),
+            },
+            // A step of 1 or less would stop zooming in from magnifying, as `set_zoom_exp` guards against
+            zoom_exp: match number("zoom_exp")? as f32 {
+                zoom_exp if (MIN_ZOOM_EXP..=MAX_ZOOM_EXP).contains(&zoom_exp) => zoom_exp,
+                _ => {
+                    return Err(format!(
+                        "'zoom_exp' must be between {} and {}",
+                        MIN_ZOOM_EXP, MAX_ZOOM_EXP
+                    ))
+                }
+            },
+            view_transform: match json.get("view_transform").and_then(Value::as_str) {
+                Some(name) => ViewTransform::from_name(name)
+                    .ok_or_else(|| format!("unknown view transform '{}'", name))?,
+                None => ViewTransform::Linear,
+            },
+            y_stretch: json
+                .get("y_stretch")
+                .and_then(Value::as_f64)
+                .filter(|n| *n > 0.)
+                .map_or(1., |n| n as f32),
+            offset_x: number("offset_x")? as f32,
+            offset_y: number("offset_y")? as f32,
+            gamma: match number("gamma")? as f32 {
+                gamma if gamma.is_finite() && gamma > 0. => gamma,
+                _ => return Err("'gamma' must be a positive number".to_string()),
+            },
+            exterior_lift: json
+                .get("exterior_lift")
+                .and_then(Value::as_f64)
+                .map_or(0., |n| (n as f32).clamp(-1., 1.)),
+            // Files from before the shapes had a flag for the diamond
+            escape_shape: match json.get("escape_shape").and_then(Value::as_str) {
+                Some(name) => EscapeShape::from_name(name)
+                    .ok_or_else(|| format!("unknown escape shape '{}'", name))?,
+                None if flag("fast_escape") => EscapeShape::Diamond,
+                None => EscapeShape::Circle,
+            },
+            bailout: json
+                .get("bailout")
+                .and_then(Value::as_f64)
+                .filter(|n| !n.is_nan())
+                .map_or(2., |n| (n as f32).clamp(MIN_BAILOUT, MAX_BAILOUT)),
+            contours: flag("contours"),
+            edge_blend: flag("edge_blend"),
+            colour_mode: match json.get("colour_mode").and_then(Value::as_str) {
+                Some(name) => ColourMode::from_name(name)
+                    .ok_or_else(|| format!("unknown colour mode '{}'", name))?,
+                None => ColourMode::Bands,
+            },
+            smooth_extra_iterations: json
+                .get("smooth_extra_iterations")
+                .and_then(Value::as_f64)
+                .map_or(2, |n| (n as u32).min(MAX_SMOOTH_EXTRA_ITERATIONS)),
+            log_colour: flag("log_colour"),
+            colour_period: json
+                .get("colour_period")
+                .and_then(Value::as_f64)
+                .map(|n| n as f32)
+                .filter(|n| *n > 0. && n.is_finite()),
+            colour_offset: json
+                .get("colour_offset")
+                .and_then(Value::as_f64)
+                .map_or(0., |n| (n as f32).rem_euclid(1.)),
+            supersample: json
+                .get("supersample")
+                .and_then(Value::as_f64)
+                .map_or(1, |n| (n as u32).max(1)),
+            downscale_filter: json
+                .get("downscale_filter")
+                .and_then(Value::as_str)
+                .and_then(DownscaleFilter::from_name)
+                .unwrap_or(DownscaleFilter::Lanczos3),
+            // Sidecars from before the setting existed were rendered on the regular grid
+            sample_pattern: json
+                .get("sample_pattern")
+                .and_then(Value::as_str)
+                .and_then(SamplePattern::from_name)
+                .unwrap_or(SamplePattern::Grid),
+            sample_seed: json
+                .get("sample_seed")
+                .and_then(Value::as_f64)
+                .map_or(0, |n| n as u32),
+            precision: match json.get("precision").and_then(Value::as_str) {
+                Some(name) => Precision::from_name(name)
+                    .ok_or_else(|| format!("unknown precision '{}'", name))?,
+                None => Precision::Auto,
+            },
+            f64_region: match json.get("f64_region").and_then(Value::as_array) {
+                Some(corners) => match corners
+                    .iter()
+                    .map(Value::as_f64)
+                    .collect::<Option<Vec<_>>>()
+                {
+                    Some(corners) if corners.len() == 4 => Some(Region {
+                        x0: corners[0] as u32,
+                        y0: corners[1] as u32,
+                        x1: corners[2] as u32,
+                        y1: corners[3] as u32,
+                    }),
+                    _ => return Err("'f64_region' must be [x0, y0, x1, y1]".to_string()),
+                },
+                None => None,
+            },
+            importance: json
+                .get("importance")
+                .and_then(Value::as_str)
+                .and_then(|path| load_importance_or_warn(Path::new(path))),
+            palette: json
+                .get("palette")
+                .and_then(Value::as_str)
+                .and_then(|path| load_palette_or_warn(Path::new(path))),
+            blend_palette: json
+                .get("blend_palette")
+                .and_then(Value::as_str)
+                .and_then(|path| load_palette_or_warn(Path::new(path))),
+            palette_blend: json
+                .get("palette_blend")
+                .and_then(Value::as_f64)
+                .map_or(0.5, |n| (n as f32).clamp(0., 1.)),
+            interior_mode: match json.get("interior_mode").and_then(Value::as_str) {
+                Some(name) => InteriorMode::from_name(name)
+                    .ok_or_else(|| format!("unknown interior mode '{}'", name))?,
+                None => InteriorMode::Flat,
+            },
+            interior_colour: match json.get("interior_colour") {
+                Some(colour) => Some(colour_from_json(colour, "interior_colour")?),
+                None => None,
+            },
+            degenerate_colour: match json.get("degenerate_colour") {
+                Some(colour) => colour_from_json(colour, "degenerate_colour")?,
+                None => DEGENERATE_COLOUR,
+            },
+            transparency: match json.get("transparency").and_then(Value::as_str) {
+                Some(name) => Transparency::from_name(name)
+                    .ok_or_else(|| format!("unknown transparency '{}'", name))?,
+                None => Transparency::Opaque,
+            },
+        })
+    }
+}
+
+/// Store an opaque colour as `[r, g, b]`.
+fn colour_to_json([r, g, b, _]: [u8; 4]) -> Value {
+    Value::Array(vec![
+        Value::Number(r as f64),
+        Value::Number(g as f64),
+        Value::Number(b as f64),
+    ])
+}
+
+/// Read a colour written by `colour_to_json`.
+pub(crate) fn colour_from_json(json: &Value, key: &str) -> Result<[u8; 4], String> {
+    let channel = |value: &Value| {
+        value
+            .as_f64()
+            .filter(|n| (0. ..=255.).contains(n))
+            .map(|n| n as u8)
+    };
+    match json.as_array() {
+        Some([r, g, b]) => match (channel(r), channel(g), channel(b)) {
+            (Some(r), Some(g), Some(b)) => Ok([r, g, b, 255]),
+            _ => Err(format!("'{}' channels must be from 0 to 255", key)),
+        },
+        _ => Err(format!("'{}' must be [r, g, b]", key)),
+    }
+}
+
+/// Load a palette, warning and falling back to grayscale (`None`) if it can't be read.
+/// Names of built-in palettes are recognised before files.
+pub fn load_palette_or_warn(path: &Path) -> Option<Arc<Palette>> {
+    if let Some(palette) = path.to_str().and_then(Palette::builtin) {
+        return Some(Arc::new(palette));
+    }
+    match Palette::load(path) {
+        Ok(palette) => Some(Arc::new(palette)),
+        // Most likely a mistyped built-in name, so list them
+        Err(_) if !path.exists() => {
+            eprintln!(
+                "warning: unknown palette '{}', expected a palette file or one of: {}, using grayscale",
+                path.display(),
+                Palette::builtin_names().join(", ")
+            );
+            None
+        }
+        Err(message) => {
+            eprintln!("warning: {}, using grayscale", message);
+            None
+        }
+    }
+}
+
+/// Load an importance map, warning and falling back to the same iterations everywhere (`None`) if it can't be read.
+pub fn load_importance_or_warn(path: &Path) -> Option<Arc<ImportanceMap>> {
+    match ImportanceMap::from_image(path) {
+        Ok(map) => Some(Arc::new(map)),
+        Err(message) => {
+            eprintln!("warning: {}, iterating every pixel alike", message);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::render;
+
+    #[test]
+    fn sidecar_round_trip_renders_the_same_image() {
+        let settings = MandelbrotSettings {
+            fractal: FractalKind::Julia,
+            width: 48,
+            height: 32,
+            max_iterations: 150,
+            julia_c: Complex::new(-0.8, 0.156),
+            zoom: 1.7,
+            offset_x: 0.1,
+            offset_y: -0.22,
+            colour_mode: ColourMode::Smooth,
+            contours: true,
+            ..MandelbrotSettings::default()
+        };
+        let text = settings.to_json().to_pretty_string();
+        let json = Value::parse(&text).unwrap();
+        assert_eq!(json.get("width").and_then(Value::as_f64), Some(48.));
+        assert_eq!(json.get("height").and_then(Value::as_f64), Some(32.));
+        assert_eq!(json.get("fractal").and_then(Value::as_str), Some("julia"));
+
+        let reloaded = MandelbrotSettings::from_json(&json).unwrap();
+        assert_eq!(reloaded.to_json(), settings.to_json());
+        assert_eq!(render(&reloaded).as_raw(), render(&settings).as_raw());
+    }
+
+    #[test]
+    fn unknown_palettes_fall_back_to_grayscale() {
+        assert!(load_palette_or_warn(Path::new("fire")).is_some());
+        assert!(load_palette_or_warn(Path::new("no-such-palette")).is_none());
+    }
+
+    #[test]
+    fn sidecar_max_iterations_must_be_at_least_1() {
+        let mut json = MandelbrotSettings::default().to_json();
+        if let Value::Object(map) = &mut json {
+            map.insert("max_iterations".to_string(), Value::Number(0.));
+        }
+        assert!(MandelbrotSettings::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn sidecar_zoom_must_be_positive() {
+        for zoom in [0., -1.5, 1e300] {
+            let mut json = MandelbrotSettings::default().to_json();
+            if let Value::Object(map) = &mut json {
+                map.insert("zoom".to_string(), Value::Number(zoom));
+            }
+            assert!(MandelbrotSettings::from_json(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn sidecar_zoom_step_and_gamma_must_be_in_range() {
+        for (key, value) in [
+            ("zoom_exp", 1.),
+            ("zoom_exp", 0.5),
+            ("zoom_exp", 50.),
+            ("gamma", 0.),
+            ("gamma", -0.2),
+            ("gamma", 1e300),
+        ] {
+            let mut json = MandelbrotSettings::default().to_json();
+            if let Value::Object(map) = &mut json {
+                map.insert(key.to_string(), Value::Number(value));
+            }
+            assert!(
+                MandelbrotSettings::from_json(&json).is_err(),
+                "{} {}",
+                key,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn the_pixel_scale_stays_normal_down_to_the_precision_limit() {
+        // The exponential view stays in f32 throughout, the linear one goes on into f64, and at 0 only the
+        // pixel step itself can run out
+        for (view_transform, offset_x, offset_y) in [
+            (ViewTransform::Exponential, -0.7436439, 0.1318259),
+            (ViewTransform::Linear, -0.7436439, 0.1318259),
+            (ViewTransform::Linear, 0., 0.),
+        ] {
+            let mut settings = MandelbrotSettings {
+                view_transform,
+                offset_x,
+                offset_y,
+                ..MandelbrotSettings::default()
+            };
+            let zoom_exp = settings.zoom_exp;
+            let mut steps = 0;
+            while settings.zoom_by(zoom_exp) {
+                steps += 1;
+                assert!(steps < 1000, "{:?} never reached a limit", view_transform);
+            }
+            assert!(!settings.is_resolvable(settings.zoom * zoom_exp));
+            for scale in settings.pixel_scale() {
+                assert!(scale.is_normal(), "{} at zoom {}", scale, settings.zoom);
+            }
+            if view_transform == ViewTransform::Linear && offset_x != 0. {
+                assert!(
+                    settings.needs_f64(),
+                    "the last zooms should have gone into f64"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_world_width_round_trips_and_matches_the_pixel_scale() {
+        let mut settings = MandelbrotSettings {
+            width: 120,
+            height: 70,
+            y_stretch: 1.5,
+            ..MandelbrotSettings::default()
+        };
+        for width in [4., 1., 0.37, 2.5e-4] {
+            settings.set_world_width(width).unwrap();
+            assert!((settings.world_width() - width).abs() <= width * 1e-6);
+            let [width_scale, height_scale] = settings.pixel_scale();
+            assert_eq!(settings.world_width(), width_scale * settings.width as f32);
+            assert_eq!(
+                settings.world_height(),
+                height_scale * settings.height as f32
+            );
+        }
+        for width in [0., -1., f32::NAN] {
+            assert!(settings.set_world_width(width).is_err());
+        }
+    }
+
+    #[test]
+    fn corners_bracket_the_centre_and_span_the_view() {
+        for (zoom, y_stretch, offset_x, offset_y) in [
+            (1., 1., -0.5, 0.),
+            (3.7, 1., -0.75, 0.1),
+            (250., 2.5, 0.3, -0.6),
+        ] {
+            let settings = MandelbrotSettings {
+                width: 90,
+                height: 50,
+                zoom,
+                y_stretch,
+                offset_x,
+                offset_y,
+                ..MandelbrotSettings::default()
+            };
+            let [top_left, top_right, bottom_right, bottom_left] = settings.corners();
+            assert!(top_left.re < offset_x && offset_x < bottom_right.re);
+            assert!(top_left.im < offset_y && offset_y < bottom_right.im);
+            // The flat view isn't rotated, so the corners share their sides' coordinates
+            assert_eq!(
+                [top_left.re, top_right.re],
+                [bottom_left.re, bottom_right.re]
+            );
+            assert_eq!(
+                [top_left.im, bottom_left.im],
+                [top_right.im, bottom_right.im]
+            );
+            // The span doesn't depend on the image's shape, only on the zoom and stretch
+            let span = bottom_right - top_left;
+            let tolerance = 1e-5 * WORLD_SPAN / zoom;
+            assert!((span.re - WORLD_SPAN / zoom).abs() < tolerance, "{}", span);
+            assert!(
+                (span.im - WORLD_SPAN / (zoom * y_stretch)).abs() < tolerance,
+                "{}",
+                span
+            );
+        }
+    }
+
+    #[test]
+    fn the_view_matrix_maps_pixels_like_pixel_to_complex() {
+        let settings = MandelbrotSettings {
+            width: 90,
+            height: 50,
+            zoom: 250.,
+            y_stretch: 2.5,
+            offset_x: 0.3,
+            offset_y: -0.6,
+            ..MandelbrotSettings::default()
+        };
+        let m = settings.view_matrix().unwrap();
+        let apply = |x: f32, y: f32| {
+            Complex::new(
+                m[0][0] * x + m[0][1] * y + m[0][2],
+                m[1][0] * x + m[1][1] * y + m[1][2],
+            )
+        };
+        // The outer corners of the image, and a pixel in between
+        let [top_left, _, bottom_right, _] = settings.corners();
+        let tolerance = 1e-6;
+        for (pixel, expected) in [
+            ((0., 0.), top_left),
+            ((90., 50.), bottom_right),
+            ((17., 31.), pixel_to_complex(&settings, 17., 31.)),
+        ] {
+            let mapped = apply(pixel.0, pixel.1);
+            assert!(
+                (mapped - expected).norm() < tolerance,
+                "{:?}: {} vs {}",
+                pixel,
+                mapped,
+                expected
+            );
+        }
+
+        let exponential = MandelbrotSettings {
+            view_transform: ViewTransform::Exponential,
+            ..settings
+        };
+        assert_eq!(exponential.view_matrix(), None);
+    }
+}
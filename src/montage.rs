@@ -0,0 +1,94 @@
+use crate::mandelbrot_settings::MandelbrotSettings;
+use crate::render::render;
+use image::{imageops, ImageBuffer, Rgba};
+
+/// Scale of the label font, each of its pixels drawn as a square this size.
+const LABEL_SCALE: u32 = 2;
+/// Height of the strip under each cell that holds its label.
+const LABEL_HEIGHT: u32 = 9 * LABEL_SCALE;
+const LABEL_COLOUR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const SHEET_BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+
+/// Render each view as a `cell_width` x `cell_height` thumbnail and arrange them left to right, top to bottom in a
+/// grid `columns` wide, each labelled with its centre coordinate. Useful for reviewing a set of saved views at once.
+/// The views keep their own colouring and iterations; only the size changes, so each shows the same area.
+pub fn contact_sheet(
+    views: &[MandelbrotSettings],
+    columns: u32,
+    cell_width: u32,
+    cell_height: u32,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let columns = columns.max(1);
+    let rows = (views.len() as u32).div_ceil(columns);
+    let pitch_y = cell_height + LABEL_HEIGHT;
+    let mut sheet = ImageBuffer::from_pixel(columns * cell_width, rows * pitch_y, SHEET_BACKGROUND);
+
+    for (index, view) in views.iter().enumerate() {
+        let (x, y) = (
+            index as u32 % columns * cell_width,
+            index as u32 / columns * pitch_y,
+        );
+        let cell = render(&MandelbrotSettings {
+            width: cell_width,
+            height: cell_height,
+            ..view.clone()
+        });
+        imageops::replace(&mut sheet, &cell, x as i64, y as i64);
+        let label = format!("{} {}", view.offset_x, view.offset_y);
+        draw_label(
+            &mut sheet,
+            &label,
+            x + LABEL_SCALE,
+            y + cell_height + 2 * LABEL_SCALE,
+            x + cell_width,
+        );
+    }
+    sheet
+}
+
+/// Draw `text` with its top left corner at `x`, `y`, stopping at `right` so long labels don't spill into the next cell.
+/// Only the characters in coordinates are drawn; anything else is left as a space.
+fn draw_label(sheet: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, text: &str, x: u32, y: u32, right: u32) {
+    for (index, character) in text.chars().enumerate() {
+        let left = x + index as u32 * 4 * LABEL_SCALE; // 3 pixels wide plus a 1 pixel gap
+        if left + 3 * LABEL_SCALE > right {
+            break;
+        }
+        let glyph = glyph(character);
+        for row in 0..5 {
+            for column in 0..3 {
+                if glyph >> (14 - row * 3 - column) & 1 == 0 {
+                    continue;
+                }
+                for dy in 0..LABEL_SCALE {
+                    for dx in 0..LABEL_SCALE {
+                        let (px, py) =
+                            (left + column * LABEL_SCALE + dx, y + row * LABEL_SCALE + dy);
+                        if px < sheet.width() && py < sheet.height() {
+                            sheet.put_pixel(px, py, LABEL_COLOUR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A 3x5 pixel glyph, the rows packed top first into the low 15 bits with the leftmost pixel highest.
+fn glyph(character: char) -> u16 {
+    match character {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_001_001_001,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        '-' => 0b000_000_111_000_000,
+        '.' => 0b000_000_000_000_010,
+        _ => 0,
+    }
+}
@@ -0,0 +1,215 @@
+use mandelbrot_test::mandelbrot_settings::MandelbrotSettings;
+use mandelbrot_test::render::{complex_to_pixel, pixel_to_complex, ViewTransform};
+use num_complex::Complex;
+use piston_window::{line, rectangle, Context, Graphics};
+
+const GRID_COLOUR: [f32; 4] = [0.3, 0.6, 1.0, 0.35];
+const AXIS_COLOUR: [f32; 4] = [0.3, 0.6, 1.0, 0.9];
+const HEATMAP_TILE: u32 = 32; // Size in output pixels of each heatmap cell
+const MEASURE_COLOUR: [f32; 4] = [1.0, 0.85, 0.2, 0.9];
+const MEASURE_MARKER: f64 = 5.; // Width in output pixels of the square marking each measured point
+const PROBE_COLOUR: [f32; 4] = [0.2, 1.0, 0.4, 0.9];
+const PROBE_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const PROBE_PANEL: f64 = 0.25; // Fraction of the view's height (or width) taken by the probe's graph
+
+/// A straight slice through the view whose iteration counts `draw_probe` plots.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Probe {
+    Row(u32),    // Horizontal line across the view at this pixel row
+    Column(u32), // Vertical line down the view at this pixel column
+}
+
+/// Draw the real and imaginary axes, with gridlines at round intervals, over the current view.
+/// The spacing is a power of ten chosen so there are roughly 4 to 40 lines across the view whatever the zoom.
+pub fn draw_grid<G: Graphics>(settings: &MandelbrotSettings, context: Context, graphics: &mut G) {
+    if settings.view_transform != ViewTransform::Linear {
+        return; // The gridlines would be curves, which this doesn't draw
+    }
+    let (width, height) = (settings.width as f32, settings.height as f32);
+    let top_left = pixel_to_complex(settings, 0., 0.);
+    let bottom_right = pixel_to_complex(settings, width, height);
+    let span = (bottom_right.re - top_left.re).max(bottom_right.im - top_left.im);
+    let spacing = 10f32.powf((span / 4.).log10().floor());
+    if !(spacing.is_normal() && span.is_finite()) {
+        return; // Nothing sensible to draw for a degenerate view
+    }
+
+    // Step over whole multiples of the spacing, so the axes (multiple 0) are found exactly and the
+    // loop always ends even when `spacing` is tiny next to the coordinates
+    let first_re = (top_left.re / spacing).floor() as i64;
+    let last_re = (bottom_right.re / spacing).ceil() as i64;
+    for k in first_re..=last_re {
+        let [x, _] = complex_to_pixel(settings, Complex::new(k as f32 * spacing, 0.));
+        let colour = if k == 0 { AXIS_COLOUR } else { GRID_COLOUR };
+        let x = x as f64;
+        line(
+            colour,
+            0.5,
+            [x, 0., x, height as f64],
+            context.transform,
+            graphics,
+        );
+    }
+
+    let first_im = (top_left.im / spacing).floor() as i64;
+    let last_im = (bottom_right.im / spacing).ceil() as i64;
+    for k in first_im..=last_im {
+        let [_, y] = complex_to_pixel(settings, Complex::new(0., k as f32 * spacing));
+        let colour = if k == 0 { AXIS_COLOUR } else { GRID_COLOUR };
+        let y = y as f64;
+        line(
+            colour,
+            0.5,
+            [0., y, width as f64, y],
+            context.transform,
+            graphics,
+        );
+    }
+}
+
+/// Shade the view in tiles by their average iteration count, from blue for cheap tiles to red for tiles
+/// where most pixels run to the limit, to show where render time goes. `counts` are the iteration counts
+/// the image was rendered from, at the `settings.supersampled()` resolution.
+pub fn draw_heatmap<G: Graphics>(
+    counts: &[f32],
+    settings: &MandelbrotSettings,
+    opacity: f32,
+    context: Context,
+    graphics: &mut G,
+) {
+    let n = settings.supersample.max(1);
+    let columns = (settings.width * n) as usize;
+    let tile = HEATMAP_TILE * n; // Tile size in count buffer pixels
+    for tile_y in (0..settings.height * n).step_by(tile as usize) {
+        for tile_x in (0..settings.width * n).step_by(tile as usize) {
+            // Average the counts under the tile, leaving out degenerate pixels
+            let (mut total, mut pixels) = (0., 0);
+            for y in tile_y..(tile_y + tile).min(settings.height * n) {
+                for x in tile_x..(tile_x + tile).min(settings.width * n) {
+                    let count = counts[y as usize * columns + x as usize];
+                    if count.is_finite() {
+                        total += count;
+                        pixels += 1;
+                    }
+                }
+            }
+            if pixels == 0 {
+                continue;
+            }
+            let t = (total / pixels as f32 / settings.max_iterations as f32).clamp(0., 1.);
+            let rect = [
+                (tile_x / n) as f64,
+                (tile_y / n) as f64,
+                HEATMAP_TILE as f64,
+                HEATMAP_TILE as f64,
+            ];
+            rectangle([t, 0.2, 1. - t, opacity], rect, context.transform, graphics);
+        }
+    }
+}
+
+/// Mark the clicked measurement points and join them with a line. The points are coordinates, so they stay on
+/// the features they were placed on as the view moves. In the exponential view the straight segment between
+/// them would be a curve, so only the points are marked.
+pub fn draw_measurement<G: Graphics>(
+    settings: &MandelbrotSettings,
+    points: &[Complex<f32>],
+    context: Context,
+    graphics: &mut G,
+) {
+    let pixels: Vec<[f64; 2]> = points
+        .iter()
+        .map(|&c| complex_to_pixel(settings, c).map(|p| p as f64))
+        .collect();
+    if let ([a, b], ViewTransform::Linear) = (&pixels[..], settings.view_transform) {
+        line(
+            MEASURE_COLOUR,
+            0.75,
+            [a[0], a[1], b[0], b[1]],
+            context.transform,
+            graphics,
+        );
+    }
+    for [x, y] in pixels {
+        let half = MEASURE_MARKER / 2.;
+        rectangle(
+            MEASURE_COLOUR,
+            [x - half, y - half, MEASURE_MARKER, MEASURE_MARKER],
+            context.transform,
+            graphics,
+        );
+    }
+}
+
+/// Draw the probe line and, along the edge of the view, a graph of the iteration counts under it: a panel along
+/// the bottom for a row and down the right for a column, lined up with the pixels it plots. Escaping at once is
+/// at the panel's outer edge and the interior at its inner one. `counts` are the iteration counts the image was
+/// rendered from, as for `draw_heatmap`; degenerate pixels leave gaps in the graph.
+pub fn draw_probe<G: Graphics>(
+    counts: &[f32],
+    settings: &MandelbrotSettings,
+    probe: Probe,
+    context: Context,
+    graphics: &mut G,
+) {
+    let n = settings.supersample.max(1);
+    let columns = (settings.width * n) as usize;
+    let (width, height) = (settings.width as f64, settings.height as f64);
+    // Each pixel's first sample, or `None` where there's nothing to plot. The counts can be from an older
+    // render than the settings while frozen, so indices past the end are skipped rather than trusted
+    let count_at = |x: u32, y: u32| {
+        let count = *counts.get((y * n) as usize * columns + (x * n) as usize)?;
+        count
+            .is_finite()
+            .then(|| (count / settings.max_iterations as f32).clamp(0., 1.) as f64)
+    };
+
+    // Sample along the line, placing each count across the panel
+    let (points, panel): (Vec<Option<[f64; 2]>>, [f64; 4]) = match probe {
+        Probe::Row(row) => {
+            let row = row.min(settings.height.saturating_sub(1));
+            let y = row as f64 + 0.5;
+            line(
+                PROBE_COLOUR,
+                0.5,
+                [0., y, width, y],
+                context.transform,
+                graphics,
+            );
+            let depth = height * PROBE_PANEL;
+            let points = (0..settings.width)
+                .map(|x| count_at(x, row).map(|level| [x as f64 + 0.5, height - level * depth]))
+                .collect();
+            (points, [0., height - depth, width, depth])
+        }
+        Probe::Column(column) => {
+            let column = column.min(settings.width.saturating_sub(1));
+            let x = column as f64 + 0.5;
+            line(
+                PROBE_COLOUR,
+                0.5,
+                [x, 0., x, height],
+                context.transform,
+                graphics,
+            );
+            let depth = width * PROBE_PANEL;
+            let points = (0..settings.height)
+                .map(|y| count_at(column, y).map(|level| [width - level * depth, y as f64 + 0.5]))
+                .collect();
+            (points, [width - depth, 0., depth, height])
+        }
+    };
+
+    rectangle(PROBE_BACKGROUND, panel, context.transform, graphics);
+    for pair in points.windows(2) {
+        if let [Some(a), Some(b)] = pair {
+            line(
+                PROBE_COLOUR,
+                0.5,
+                [a[0], a[1], b[0], b[1]],
+                context.transform,
+                graphics,
+            );
+        }
+    }
+}
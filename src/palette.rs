@@ -0,0 +1,220 @@
+use crate::gradient::Gradient;
+use crate::json::Value;
+use image::Rgba;
+use std::path::{Path, PathBuf};
+
+/// A colour ramp sampled by the normalised iteration value `t`.
+pub struct Palette {
+    pub gradient: Gradient,
+    pub source: Option<PathBuf>, // File (or built-in name) the palette was loaded from, so saved settings can refer to it
+}
+
+/// The text of a palette file, naming it in the error if it can't be read.
+fn read_palette_text(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read palette {}: {}", path.display(), e))
+}
+
+/// The lines of `text` that aren't blank, trimmed, each with its line number counting from 1 for warnings.
+fn numbered_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+/// Built-in gradients usable by name instead of a file.
+const BUILTIN_PALETTES: [(&str, Gradient); 3] = [
+    ("fire", Gradient::FIRE),
+    ("ice", Gradient::ICE),
+    ("rainbow", Gradient::RAINBOW),
+];
+
+impl Palette {
+    /// A palette made in code rather than loaded, with no `source`, so saved settings leave it out.
+    pub fn new(gradient: Gradient) -> Palette {
+        Palette {
+            gradient,
+            source: None,
+        }
+    }
+
+    /// Names of the built-in palettes, for error messages.
+    pub fn builtin_names() -> Vec<&'static str> {
+        BUILTIN_PALETTES.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// A built-in palette by name. Its `source` is the name, so saved settings refer back to it.
+    pub fn builtin(name: &str) -> Option<Palette> {
+        let (name, gradient) = BUILTIN_PALETTES.iter().find(|(n, _)| *n == name)?;
+        Some(Palette {
+            gradient: gradient.clone(),
+            source: Some(PathBuf::from(name)),
+        })
+    }
+
+    /// Load a palette from a file: a gradient of stops if it ends in `.json` (see `Gradient::from_json`), a GIMP
+    /// palette if it ends in `.gpl`, a list of colours if it ends in `.csv`, otherwise a gradient strip image.
+    pub fn load(path: &Path) -> Result<Palette, String> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("json") => Palette::from_json_file(path),
+            Some("gpl") => Palette::from_gpl_file(path),
+            Some("csv") => Palette::from_csv_file(path),
+            _ => Palette::from_image(path),
+        }
+    }
+
+    /// Load a palette from a gradient strip image, 1 pixel high or wide and any length, its pixels spread evenly
+    /// along the ramp.
+    pub fn from_image(path: &Path) -> Result<Palette, String> {
+        let img = image::open(path)
+            .map_err(|e| format!("failed to load palette {}: {}", path.display(), e))?
+            .to_rgba8();
+
+        // Read along whichever dimension is the length of the strip
+        let colours: Vec<Rgba<u8>> = match img.dimensions() {
+            (width, 1) => (0..width).map(|x| *img.get_pixel(x, 0)).collect(),
+            (1, height) => (0..height).map(|y| *img.get_pixel(0, y)).collect(),
+            (width, height) => {
+                return Err(format!(
+                    "palette {} is {}x{}, expected a 1xN or Nx1 strip",
+                    path.display(),
+                    width,
+                    height
+                ))
+            }
+        };
+
+        if colours.is_empty() {
+            return Err(format!("palette {} has no colours", path.display()));
+        }
+
+        Ok(Palette {
+            gradient: Gradient::evenly_spaced(colours)?,
+            source: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Load a palette from a JSON file of gradient stops.
+    pub fn from_json_file(path: &Path) -> Result<Palette, String> {
+        let text = read_palette_text(path)?;
+        let json = Value::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Palette {
+            gradient: Gradient::from_json(&json)
+                .map_err(|e| format!("palette {}: {}", path.display(), e))?,
+            source: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Load a palette from a GIMP palette file: a `GIMP Palette` header, maybe `Name:` and `Columns:` lines and
+    /// `#` comments, then a colour per line as red, green and blue from 0 to 255, usually followed by its name.
+    /// The colours are spread evenly along the ramp in the order listed, as in a strip image.
+    pub fn from_gpl_file(path: &Path) -> Result<Palette, String> {
+        let text = read_palette_text(path)?;
+        let mut lines = numbered_lines(&text);
+        match lines.next() {
+            Some((_, "GIMP Palette")) => {}
+            _ => {
+                return Err(format!(
+                    "palette {} isn't a GIMP palette, it should start with 'GIMP Palette'",
+                    path.display()
+                ))
+            }
+        }
+        let colours = lines.filter(|(_, line)| {
+            !(line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:"))
+        });
+        Palette::from_colour_lines(path, colours, |line| line.split_whitespace().collect())
+    }
+
+    /// Load a palette from a CSV file of colours, one `r,g,b` per line from 0 to 255, spread evenly along the ramp
+    /// in the order listed. Columns after the third are ignored, as are `#` comments and a header line of names.
+    pub fn from_csv_file(path: &Path) -> Result<Palette, String> {
+        let text = read_palette_text(path)?;
+        let mut lines = numbered_lines(&text).peekable();
+        // A header names the columns, so has no digits at all
+        if lines
+            .peek()
+            .is_some_and(|(_, line)| !line.contains(|c: char| c.is_ascii_digit()))
+        {
+            lines.next();
+        }
+        let colours = lines.filter(|(_, line)| !line.starts_with('#'));
+        Palette::from_colour_lines(path, colours, |line| {
+            line.split(',').map(str::trim).collect()
+        })
+    }
+
+    /// Read a colour from the first three of the `fields` of each line, all from 0 to 255. A line that isn't one is
+    /// skipped with a warning rather than losing the whole palette, which only fails if no colours are left.
+    fn from_colour_lines<'a>(
+        path: &Path,
+        lines: impl Iterator<Item = (usize, &'a str)>,
+        fields: impl Fn(&str) -> Vec<&str>,
+    ) -> Result<Palette, String> {
+        let mut colours = Vec::new();
+        for (number, line) in lines {
+            let channels: Option<Vec<u8>> = fields(line)
+                .get(..3)
+                .and_then(|rgb| rgb.iter().map(|channel| channel.parse().ok()).collect());
+            match channels {
+                Some(rgb) => colours.push(Rgba([rgb[0], rgb[1], rgb[2], 255])),
+                None => eprintln!(
+                    "warning: palette {} line {}: expected red, green and blue from 0 to 255, skipping '{}'",
+                    path.display(),
+                    number,
+                    line
+                ),
+            }
+        }
+        if colours.is_empty() {
+            return Err(format!("palette {} has no colours", path.display()));
+        }
+        Ok(Palette {
+            gradient: Gradient::evenly_spaced(colours)?,
+            source: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Colour at `t` between 0 and 1, linearly interpolating between neighbouring stops.
+    pub fn sample(&self, t: f32) -> [u8; 4] {
+        self.gradient.sample(t).0
+    }
+
+    /// Like `sample`, but without rounding the channels (still from 0 to 255), for output deeper than 8 bits.
+    pub fn sample_wide(&self, t: f32) -> [f32; 4] {
+        self.gradient.sample_wide(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_gimp_palette_gives_its_colours_in_order() {
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/palettes/sunset.gpl"
+        ));
+        let palette = Palette::load(path).unwrap();
+        // The malformed line is skipped, leaving the four colours evenly spaced
+        let expected = [
+            (0., [20, 12, 64]),
+            (1. / 3., [120, 30, 90]),
+            (2. / 3., [230, 90, 40]),
+            (1., [255, 220, 120]),
+        ];
+        let stops = palette.gradient.stops();
+        assert_eq!(stops.len(), expected.len());
+        for ((position, colour), (expected_position, [r, g, b])) in stops.iter().zip(expected) {
+            assert!((position - expected_position).abs() < 1e-6);
+            assert_eq!(*colour, Rgba([r, g, b, 255]));
+        }
+        assert_eq!(palette.source.as_deref(), Some(path));
+    }
+}
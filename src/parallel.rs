@@ -0,0 +1,29 @@
+//! The few rayon calls the renderer makes. With the `parallel` feature they come straight from rayon;
+//! without it the same names run sequentially on the calling thread, for targets without threads like wasm32.
+
+#[cfg(feature = "parallel")]
+pub use rayon::prelude::*;
+
+#[cfg(not(feature = "parallel"))]
+pub trait ParallelSliceMut<T> {
+    fn par_chunks_mut(&mut self, chunk_size: usize) -> std::slice::ChunksMut<'_, T>;
+}
+
+#[cfg(not(feature = "parallel"))]
+impl<T> ParallelSliceMut<T> for [T] {
+    fn par_chunks_mut(&mut self, chunk_size: usize) -> std::slice::ChunksMut<'_, T> {
+        self.chunks_mut(chunk_size)
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+pub trait IntoParallelRefIterator<T> {
+    fn par_iter(&self) -> std::slice::Iter<'_, T>;
+}
+
+#[cfg(not(feature = "parallel"))]
+impl<T> IntoParallelRefIterator<T> for [T] {
+    fn par_iter(&self) -> std::slice::Iter<'_, T> {
+        self.iter()
+    }
+}
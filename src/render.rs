@@ -0,0 +1,2167 @@
+use crate::fractal::{tricorn_step, FractalKind};
+use crate::mandelbrot_settings::MandelbrotSettings;
+use crate::parallel::*;
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Pixel, Rgba}; // Image library
+use num_complex::Complex; // Complex number struct
+use num_traits::{AsPrimitive, Float};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering}; // For cancelling renders
+
+/// How supersampled renders are shrunk back to the output size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DownscaleFilter {
+    Box, // Plain average of each block of samples
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3, // Sharpest, but can ring slightly around hard edges
+}
+
+impl DownscaleFilter {
+    pub const ALL: [DownscaleFilter; 5] = [
+        DownscaleFilter::Box,
+        DownscaleFilter::Triangle,
+        DownscaleFilter::CatmullRom,
+        DownscaleFilter::Gaussian,
+        DownscaleFilter::Lanczos3,
+    ];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            DownscaleFilter::Box => "box",
+            DownscaleFilter::Triangle => "triangle",
+            DownscaleFilter::CatmullRom => "catmullrom",
+            DownscaleFilter::Gaussian => "gaussian",
+            DownscaleFilter::Lanczos3 => "lanczos3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DownscaleFilter> {
+        DownscaleFilter::ALL
+            .into_iter()
+            .find(|filter| filter.name() == name)
+    }
+
+    /// Shrink `img` to `width` x `height`.
+    pub fn resize<P: OutputPixel>(self, img: &Image<P>, width: u32, height: u32) -> Image<P> {
+        let filter = match self {
+            DownscaleFilter::Box => return P::thumbnail(img, width, height), // Area averaging
+            DownscaleFilter::Triangle => FilterType::Triangle,
+            DownscaleFilter::CatmullRom => FilterType::CatmullRom,
+            DownscaleFilter::Gaussian => FilterType::Gaussian,
+            DownscaleFilter::Lanczos3 => FilterType::Lanczos3,
+        };
+        imageops::resize(img, width, height, filter)
+    }
+}
+
+/// Pixel types images can be rendered with: `Rgba<u8>` for display, or `Rgba<u16>` for exports where
+/// 8 bits would band on smooth gradients.
+pub trait OutputPixel: Pixel<Subpixel: Send + Sync> + Send + Sync + 'static {
+    /// Shrink by area averaging. Implemented per type because the bounds `image` puts on `thumbnail`
+    /// can't be named outside it.
+    fn thumbnail(img: &Image<Self>, width: u32, height: u32) -> Image<Self>;
+
+    /// Darken the colour channels by `factor`, leaving alpha alone.
+    fn shade(&mut self, factor: f32);
+}
+
+/// An image buffer of `P` pixels.
+pub type Image<P> = ImageBuffer<P, Vec<<P as Pixel>::Subpixel>>;
+
+impl OutputPixel for Rgba<u8> {
+    fn thumbnail(img: &Image<Self>, width: u32, height: u32) -> Image<Self> {
+        imageops::thumbnail(img, width, height)
+    }
+
+    fn shade(&mut self, factor: f32) {
+        for channel in &mut self.0[..3] {
+            *channel = (*channel as f32 * factor) as u8;
+        }
+    }
+}
+
+impl OutputPixel for Rgba<u16> {
+    fn thumbnail(img: &Image<Self>, width: u32, height: u32) -> Image<Self> {
+        imageops::thumbnail(img, width, height)
+    }
+
+    fn shade(&mut self, factor: f32) {
+        for channel in &mut self.0[..3] {
+            *channel = (*channel as f32 * factor) as u16;
+        }
+    }
+}
+
+/// Which float type points are iterated in. Only the flat view goes past f32; the others always use it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    Auto,   // f32, until the view is too deep for it and the whole view switches to f64
+    Hybrid, // An f32 pass, then f64 again for the samples on the set's boundary and within `f64_region`
+    Double, // f64 throughout, for comparison
+}
+
+impl Precision {
+    pub const ALL: [Precision; 3] = [Precision::Auto, Precision::Hybrid, Precision::Double];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Precision::Auto => "auto",
+            Precision::Hybrid => "hybrid",
+            Precision::Double => "double",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Precision> {
+        Precision::ALL
+            .into_iter()
+            .find(|precision| precision.name() == name)
+    }
+}
+
+/// Where the samples within each pixel are taken when supersampling. The samples are still coloured and
+/// shrunk as if they were on the regular grid, so the box filter matches this most closely.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplePattern {
+    Grid,    // Regular n x n grid. Edges close to horizontal or vertical get only n distinct shades
+    Rotated, // The grid sheared so no two samples share a row or column, which smooths near-axis edges
+    Poisson, // Randomly placed but kept apart, trading regular aliasing for fine noise
+}
+
+impl SamplePattern {
+    pub const ALL: [SamplePattern; 3] = [
+        SamplePattern::Grid,
+        SamplePattern::Rotated,
+        SamplePattern::Poisson,
+    ];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplePattern::Grid => "grid",
+            SamplePattern::Rotated => "rotated",
+            SamplePattern::Poisson => "poisson",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SamplePattern> {
+        SamplePattern::ALL
+            .into_iter()
+            .find(|pattern| pattern.name() == name)
+    }
+
+    /// The `n * n` sample positions of `n` x `n` supersampling, as fractions of a pixel from its top left corner.
+    /// Sample `j * n + i` is the one iterated for sub-pixel (i, j) of the supersampled grid.
+    /// `seed` picks which of the random patterns `Poisson` uses; the others ignore it.
+    pub fn positions(self, n: u32, seed: u32) -> Vec<[f32; 2]> {
+        let cells = (0..n * n).map(|k| ((k % n) as f32, (k / n) as f32));
+        let n = n as f32;
+        match self {
+            SamplePattern::Grid => cells.map(|(i, j)| [i / n, j / n]).collect(),
+            // Each sample stays in its cell but is shifted along it by the other axis' index, giving every
+            // sample its own column and row of the n^2 x n^2 grid. For 2x2 this is the usual RGSS pattern.
+            SamplePattern::Rotated => cells
+                .map(|(i, j)| [(i + (j + 0.5) / n) / n, (j + (n - i - 0.5) / n) / n])
+                .collect(),
+            SamplePattern::Poisson => best_candidate_positions(n as usize * n as usize, seed),
+        }
+    }
+}
+
+/// Approximate Poisson disc sampling with Mitchell's best candidate algorithm: each sample is the candidate furthest
+/// from those already placed. Distances wrap around the pixel, so neighbouring pixels' samples stay apart too.
+/// The candidates come from a generator seeded with `seed`, so renders with the same seed use the same pattern.
+fn best_candidate_positions(count: usize, seed: u32) -> Vec<[f32; 2]> {
+    const CANDIDATES_PER_SAMPLE: usize = 16;
+    // Seed 0 gives the pattern used before the seed was configurable. xorshift gets stuck at 0, so the one
+    // seed that would land there is nudged off it
+    let mut state: u32 = seed.wrapping_add(0x9e37_79b9).max(1);
+    let mut random = || {
+        // xorshift32, plenty for picking candidates
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state >> 8) as f32 / (1 << 24) as f32
+    };
+    let wrapped = |a: f32, b: f32| {
+        let d = (a - b).abs();
+        d.min(1. - d)
+    };
+
+    let mut positions: Vec<[f32; 2]> = Vec::with_capacity(count);
+    while positions.len() < count {
+        let mut best = [0.; 2];
+        let mut best_distance = -1.;
+        for _ in 0..CANDIDATES_PER_SAMPLE {
+            let candidate = [random(), random()];
+            let distance = positions
+                .iter()
+                .map(|p| wrapped(p[0], candidate[0]).hypot(wrapped(p[1], candidate[1])))
+                .fold(f32::INFINITY, f32::min);
+            if distance > best_distance {
+                (best, best_distance) = (candidate, distance);
+            }
+        }
+        positions.push(best);
+    }
+    positions
+}
+
+/// How pixel positions map onto the complex plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ViewTransform {
+    Linear,      // The usual flat view, `4 / zoom` wide, centred on the offset
+    Exponential, // Log-polar around the offset: angle across, log radius down, unrolling zooms into a ribbon
+    Sphere, // The plane wrapped onto the Riemann sphere, its two hemispheres side by side, infinity included
+}
+
+impl ViewTransform {
+    pub const ALL: [ViewTransform; 3] = [
+        ViewTransform::Linear,
+        ViewTransform::Exponential,
+        ViewTransform::Sphere,
+    ];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewTransform::Linear => "linear",
+            ViewTransform::Exponential => "exponential",
+            ViewTransform::Sphere => "sphere",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ViewTransform> {
+        ViewTransform::ALL
+            .into_iter()
+            .find(|transform| transform.name() == name)
+    }
+}
+
+/// How points inside the set are coloured.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InteriorMode {
+    Flat,        // One colour, `interior_colour` or the end of the ramp
+    Period, // By the period of the cycle the orbit settles into: the main cardioid 1, the largest bulb 2, and so on
+    OrbitRadius, // Along the ramp by the largest |z| the orbit reached, from 0 at the start to the end at |z| = 2
+}
+
+impl InteriorMode {
+    pub const ALL: [InteriorMode; 3] = [
+        InteriorMode::Flat,
+        InteriorMode::Period,
+        InteriorMode::OrbitRadius,
+    ];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            InteriorMode::Flat => "flat",
+            InteriorMode::Period => "period",
+            InteriorMode::OrbitRadius => "orbit-radius",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InteriorMode> {
+        InteriorMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == name)
+    }
+}
+
+/// The region outside of which a point counts as escaped. Each is the smallest of its kind containing the circle
+/// of radius `bailout`, so none lets a point escape before it must, but the further out the shape's corners
+/// reach, the later points near them escape and the more the bands take the shape's outline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EscapeShape {
+    Circle,  // |z| <= bailout, the usual test
+    Square, // |re| <= bailout and |im| <= bailout. The bands get square corners pointing along the diagonals
+    Diamond, // |re| + |im| <= bailout·√2, the cheapest test. The bands get corners along the axes
+}
+
+impl EscapeShape {
+    pub const ALL: [EscapeShape; 3] = [
+        EscapeShape::Circle,
+        EscapeShape::Square,
+        EscapeShape::Diamond,
+    ];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            EscapeShape::Circle => "circle",
+            EscapeShape::Square => "square",
+            EscapeShape::Diamond => "diamond",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EscapeShape> {
+        EscapeShape::ALL
+            .into_iter()
+            .find(|shape| shape.name() == name)
+    }
+}
+
+/// Which part of the image is left see-through, for compositing renders over other pictures.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transparency {
+    Opaque,   // Every pixel is fully opaque
+    Exterior, // Only the set shows. Escaped pixels take its colour with no alpha, or what `edge_blend` gives them
+    Interior, // Only the exterior shows. Points in the set have no alpha
+}
+
+impl Transparency {
+    pub const ALL: [Transparency; 3] = [
+        Transparency::Opaque,
+        Transparency::Exterior,
+        Transparency::Interior,
+    ];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Transparency::Opaque => "opaque",
+            Transparency::Exterior => "exterior",
+            Transparency::Interior => "interior",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Transparency> {
+        Transparency::ALL
+            .into_iter()
+            .find(|transparency| transparency.name() == name)
+    }
+}
+
+/// |z| that `InteriorMode::OrbitRadius` puts at the end of the ramp. Orbits reaching past it escape, except on
+/// the last step, which isn't tested against the bailout.
+const ORBIT_RADIUS_SCALE: f32 = 2.;
+
+/// Longest cycle looked for by `InteriorMode::Period`. Points in bulbs of longer periods keep the flat colour.
+pub const MAX_PERIOD: u32 = 64;
+/// How close the orbit must come back to where it was to count as having cycled.
+const PERIOD_TOLERANCE: f32 = 1e-4;
+/// Colours for interior periods 1, 2, 3, ..., repeating after the last.
+const PERIOD_COLOURS: [[u8; 4]; 8] = [
+    [40, 80, 200, 255],
+    [200, 60, 60, 255],
+    [60, 170, 70, 255],
+    [220, 180, 40, 255],
+    [150, 70, 190, 255],
+    [40, 180, 190, 255],
+    [230, 120, 40, 255],
+    [200, 90, 150, 255],
+];
+
+/// How iteration counts are turned into positions along the colour ramp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColourMode {
+    Bands,     // Whole escape counts, giving a distinct band per iteration
+    Smooth, // Fractional counts estimated from how far past the bailout z got, blending the bands together
+    Potential, // Levels of the exterior potential, iterated to a far larger escape radius, for smooth equipotential shading
+}
+
+impl ColourMode {
+    pub const ALL: [ColourMode; 3] = [ColourMode::Bands, ColourMode::Smooth, ColourMode::Potential];
+
+    /// Name used on the command line and in settings files.
+    pub fn name(self) -> &'static str {
+        match self {
+            ColourMode::Bands => "bands",
+            ColourMode::Smooth => "smooth",
+            ColourMode::Potential => "potential",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColourMode> {
+        ColourMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// Escape radius for `ColourMode::Potential`. The potential estimate is exact in the limit of a large radius, and
+/// by 256 the + c term no longer visibly bends it.
+const POTENTIAL_ESCAPE_RADIUS: f32 = 256.;
+/// Iterations over which `exterior_lift` fades out, so it only affects the outermost few bands.
+const EXTERIOR_FALLOFF: f32 = 2.;
+/// Brightness kept by pixels on a contour line.
+const CONTOUR_SHADE: f32 = 0.4;
+/// Fraction of the way to `max_iterations`, on the log scale, where `edge_blend` starts to act.
+const EDGE_BLEND_START: f32 = 0.5;
+/// How much of the interior's colour `edge_blend` mixes into pixels that escape right at the limit.
+const EDGE_BLEND_STRENGTH: f32 = 0.5;
+
+/// Iterate `z = step(z, c)` until `bounded` returns false or `max_iterations` is reached, returning the count and
+/// final z. Taking the step and bailout test as closures lets each combination compile to its own tight loop.
+///
+/// `bounded` must return false for a non-finite z. Comparisons with NaN are always false, so tests written as
+/// `metric <= threshold` do this for free and the loop stops as soon as z becomes NaN/inf, rather than spinning
+/// to `max_iterations`. The pixel is then marked degenerate in `render_counts`.
+#[inline(always)]
+fn escape_time<T: Real>(
+    c: Complex<T>,
+    z0: Complex<T>,
+    max_iterations: u32,
+    step: impl Fn(Complex<T>, Complex<T>) -> Complex<T>,
+    bounded: impl Fn(Complex<T>) -> bool,
+) -> (u32, Complex<T>) {
+    let mut z = z0;
+    let mut i = 0;
+    while i < max_iterations && bounded(z) {
+        z = step(z, c);
+        i += 1;
+    }
+    (i, z)
+}
+
+/// Iterate a point with the bailout chosen by the settings, for at most `max_iterations` steps.
+#[inline(always)]
+fn escape_time_with_bailout<T: Real>(
+    z0: Complex<T>,
+    c: Complex<T>,
+    settings: &MandelbrotSettings,
+    max_iterations: u32,
+    step: impl Fn(Complex<T>, Complex<T>) -> Complex<T>,
+) -> (u32, Complex<T>) {
+    let radius: T = settings.bailout.into();
+    if settings.colour_mode == ColourMode::Potential {
+        // The potential needs |z| well past 2, and a circle to measure it against, so the shape doesn't apply
+        let radius_sqr: T = (POTENTIAL_ESCAPE_RADIUS * POTENTIAL_ESCAPE_RADIUS).into();
+        escape_time(c, z0, max_iterations, step, |z| z.norm_sqr() <= radius_sqr)
+    } else {
+        match settings.escape_shape {
+            EscapeShape::Circle => {
+                let radius_sqr = radius * radius;
+                escape_time(c, z0, max_iterations, step, |z| z.norm_sqr() <= radius_sqr)
+            }
+            // Both parts are compared on their own, rather than their larger, which would pass over a NaN
+            EscapeShape::Square => escape_time(c, z0, max_iterations, step, |z| {
+                z.re.abs() <= radius && z.im.abs() <= radius
+            }),
+            // Points near the corners take an extra iteration or two, which bends the outer bands
+            EscapeShape::Diamond => {
+                let reach: T = (settings.bailout * std::f32::consts::SQRT_2).into();
+                escape_time(c, z0, max_iterations, step, |z| {
+                    z.re.abs() + z.im.abs() <= reach
+                })
+            }
+        }
+    }
+}
+
+/// Convert a pixel position (which may be fractional) to the complex coordinate it shows.
+/// Uses the same expression as `render_counts`, so whole pixels map to exactly the coordinate that was iterated.
+pub fn pixel_to_complex(settings: &MandelbrotSettings, x: f32, y: f32) -> Complex<f32> {
+    view_point(settings, linear_point(settings, x, y))
+}
+
+/// Where pixel (x, y) is in the linear view, before `settings.view_transform` moves it. The same expression
+/// `ViewPixels` uses, so the result is bit for bit the coordinate the renderer starts from.
+fn linear_point(settings: &MandelbrotSettings, x: f32, y: f32) -> Complex<f32> {
+    let [width_scale, height_scale] = settings.pixel_scale();
+    Complex::new(
+        (x - settings.width as f32 / 2.) * width_scale + settings.offset_x,
+        (y - settings.height as f32 / 2.) * height_scale + settings.offset_y,
+    )
+}
+
+/// `linear_point` of pixel (x, y) worked out in f64, for views too deep for f32.
+fn linear_point_f64(settings: &MandelbrotSettings, x: u32, y: u32) -> Complex<f64> {
+    let [width_scale, height_scale] = settings.pixel_scale().map(f64::from);
+    Complex::new(
+        (x as f64 - settings.width as f64 / 2.) * width_scale + settings.offset_x as f64,
+        (y as f64 - settings.height as f64 / 2.) * height_scale + settings.offset_y as f64,
+    )
+}
+
+/// Move a point of the linear view to where `settings.view_transform` shows that pixel.
+///
+/// The exponential map treats pixel (x, y) as the complex number w = (i (x - width / 2) - y) * 2π / width and shows
+/// centre + (2 / zoom) * e^w. Each row is then a circle around the centre, going once round across the width,
+/// with the top row as wide as the linear view and each row down 2π / width smaller in log radius. e^w is
+/// conformal, so shapes keep their proportions, and since zooming by k just shifts the rows by ln(k), the
+/// self-similar detail around the centre repeats down the image like a scroll through an endless zoom.
+///
+/// The sphere map draws two discs of radius R = min(width / 4, height / 2) pixels, touching at the middle of the
+/// image, as if looking at a unit sphere from below and from above. A pixel at (u, v) · R from a disc's centre, with
+/// ρ = √(u² + v²) ≤ 1, is the point of the sphere in front of it, and shows where the stereographic projection from
+/// the north pole takes that point: centre + (2 / zoom) · (X + iY) / (1 - Z). The left disc is the southern
+/// hemisphere, Z = -√(1 - ρ²) and (X, Y) = (u, v), which is everything within 2 / zoom of the centre, with the centre
+/// in the middle. The right disc is the northern hemisphere seen from above, Z = √(1 - ρ²) and (X, Y) = (-u, v),
+/// which is everything further out, with infinity in the middle. The discs meet on that circle, at centre + 2 / zoom.
+/// Pixels outside both discs show infinity too, the colour of a point escaping at once.
+#[inline(always)]
+fn view_point(settings: &MandelbrotSettings, linear: Complex<f32>) -> Complex<f32> {
+    match settings.view_transform {
+        ViewTransform::Linear => linear,
+        ViewTransform::Exponential => {
+            let [width_scale, height_scale] = settings.pixel_scale();
+            let centre = Complex::new(settings.offset_x, settings.offset_y);
+            let step = std::f32::consts::TAU / settings.width as f32; // Radians, and log radius, per pixel
+            let across = (linear.re - centre.re) / width_scale; // Pixels right of the centre column
+            let down = (linear.im - centre.im) / height_scale + settings.height as f32 / 2.; // Pixels below the top
+            centre + Complex::from_polar(2. / settings.zoom * (-down * step).exp(), across * step)
+        }
+        ViewTransform::Sphere => {
+            let [width_scale, height_scale] = settings.pixel_scale();
+            let centre = Complex::new(settings.offset_x, settings.offset_y);
+            let radius = sphere_radius(settings);
+            let across = (linear.re - centre.re) / width_scale; // Pixels right of the middle of the image
+            let down = (linear.im - centre.im) / height_scale; // And below it
+            let northern = across > 0.;
+            let u = (across + if northern { -radius } else { radius }) / radius;
+            let v = down / radius;
+            let rho = u.hypot(v);
+            if rho > 1. {
+                return centre + SPHERE_INFINITY;
+            }
+            let root = (1. - rho * rho).sqrt();
+            if !northern {
+                return centre + Complex::new(u, v) * (2. / settings.zoom / (1. + root));
+            }
+            // Here |X + iY| / (1 - Z) = ρ / (1 - √(1 - ρ²)), but that's (1 + √(1 - ρ²)) / ρ, which doesn't lose
+            // everything to cancellation near the pole
+            let distance = 2. / settings.zoom * (1. + root) / rho;
+            if distance >= SPHERE_INFINITY {
+                return centre + SPHERE_INFINITY; // Including the pole itself
+            }
+            centre + Complex::new(-u, v) * (distance / rho)
+        }
+    }
+}
+
+/// Stand-in for infinity in the sphere view: escapes on the first iteration, but squares without overflowing.
+const SPHERE_INFINITY: f32 = 1e18;
+
+/// Radius in pixels of each of the sphere view's two discs, as large as fits them side by side.
+fn sphere_radius(settings: &MandelbrotSettings) -> f32 {
+    (settings.width as f32 / 4.).min(settings.height as f32 / 2.)
+}
+
+/// Convert a complex coordinate to the (fractional) pixel position showing it. The inverse of `pixel_to_complex`.
+pub fn complex_to_pixel(settings: &MandelbrotSettings, c: Complex<f32>) -> [f32; 2] {
+    if settings.view_transform == ViewTransform::Exponential {
+        // Undo `view_point`: the angle gives the column and the log radius the row
+        let d = c - Complex::new(settings.offset_x, settings.offset_y);
+        let step = std::f32::consts::TAU / settings.width as f32;
+        return [
+            d.arg() / step + settings.width as f32 / 2.,
+            -(d.norm() * settings.zoom / 2.).ln() / step,
+        ];
+    }
+    if settings.view_transform == ViewTransform::Sphere {
+        // Undo `view_point`: back up onto the sphere, where ρ = 2q / (1 + q²) for q = |c - centre| · zoom / 2 on
+        // either side, then out to the disc for whichever hemisphere that is
+        let d = (c - Complex::new(settings.offset_x, settings.offset_y)) * (settings.zoom / 2.);
+        let q = d.norm();
+        let radius = sphere_radius(settings);
+        let [middle_x, middle_y] = [settings.width as f32 / 2., settings.height as f32 / 2.];
+        if q == 0. {
+            return [middle_x - radius, middle_y];
+        }
+        if !q.is_finite() {
+            return [middle_x + radius, middle_y];
+        }
+        let rho = 2. / (q + 1. / q); // 2q / (1 + q²) without squaring a huge q
+        let [x, y] = [d.re / q * rho * radius, d.im / q * rho * radius];
+        return if q <= 1. {
+            [middle_x - radius + x, middle_y + y]
+        } else {
+            [middle_x + radius - x, middle_y + y]
+        };
+    }
+    let [width_scale, height_scale] = settings.pixel_scale();
+    [
+        (c.re - settings.offset_x) / width_scale + settings.width as f32 / 2.,
+        (c.im - settings.offset_y) / height_scale + settings.height as f32 / 2.,
+    ]
+}
+
+/// A rectangle of pixels from (x0, y0) up to but not including (x1, y1), for rendering just part of a view.
+/// Each pixel gets exactly the coordinate it has in the full view, so tiles rendered separately (even on
+/// different machines) fit back together seamlessly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Region {
+    /// The whole view.
+    pub fn full(settings: &MandelbrotSettings) -> Region {
+        Region {
+            x0: 0,
+            y0: 0,
+            x1: settings.width,
+            y1: settings.height,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y1 - self.y0
+    }
+
+    /// Check the region isn't empty and lies within the view.
+    pub fn check(&self, settings: &MandelbrotSettings) -> Result<(), String> {
+        if self.x0 >= self.x1 || self.y0 >= self.y1 {
+            return Err(format!("region {} is empty", self));
+        }
+        if self.x1 > settings.width || self.y1 > settings.height {
+            return Err(format!(
+                "region {} is outside the {}x{} view",
+                self, settings.width, settings.height
+            ));
+        }
+        Ok(())
+    }
+
+    /// Split the view into `size` by `size` tiles in row-major order. Tiles on the right and bottom edges are cut
+    /// short where the view doesn't divide evenly.
+    pub fn tiles(settings: &MandelbrotSettings, size: u32) -> impl Iterator<Item = Region> {
+        let (width, height) = (settings.width, settings.height);
+        (0..height.div_ceil(size)).flat_map(move |row| {
+            (0..width.div_ceil(size)).map(move |column| Region {
+                x0: column * size,
+                y0: row * size,
+                x1: (column * size).saturating_add(size).min(width),
+                y1: (row * size).saturating_add(size).min(height),
+            })
+        })
+    }
+
+    /// The same region at `n` times the resolution, for supersampling.
+    fn scaled(self, n: u32) -> Region {
+        Region {
+            x0: self.x0 * n,
+            y0: self.y0 * n,
+            x1: self.x1 * n,
+            y1: self.y1 * n,
+        }
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x0, self.y0, self.x1, self.y1)
+    }
+}
+
+/// Lazily yields `(px, py, c)` for every pixel of a view in row-major order, where `c` is the complex
+/// coordinate the pixel shows, the same as `pixel_to_complex` gives, `view_transform` included. Nothing is
+/// rendered or allocated, so callers can run their own per-pixel logic.
+pub struct ViewPixels {
+    // Cached to avoid recalculating per pixel
+    width_scale: f32,
+    height_scale: f32,
+    half_width: f32,
+    half_height: f32,
+    offset_x: f32,
+    offset_y: f32,
+    start_x: u32,
+    end_x: u32,
+    end_y: u32,
+    // Next pixel to yield
+    x: u32,
+    y: u32,
+    yi: f32, // Imaginary part of row `y`, only recalculated when the row changes
+    transformed: Option<MandelbrotSettings>, // For `view_point`. `None` for the linear view, or to keep it linear
+}
+
+impl ViewPixels {
+    /// Every pixel of the view.
+    pub fn new(settings: &MandelbrotSettings) -> ViewPixels {
+        ViewPixels::region(settings, Region::full(settings))
+    }
+
+    /// Just row `y` of the view.
+    pub fn row(settings: &MandelbrotSettings, y: u32) -> ViewPixels {
+        ViewPixels::region(
+            settings,
+            Region {
+                y0: y,
+                y1: y + 1,
+                ..Region::full(settings)
+            },
+        )
+    }
+
+    /// Just the pixels of `region`, still with their coordinates in the full view.
+    pub fn region(settings: &MandelbrotSettings, region: Region) -> ViewPixels {
+        let width_64 = settings.width as f32;
+        let height_64 = settings.height as f32;
+        let [width_scale, height_scale] = settings.pixel_scale();
+        let mut pixels = ViewPixels {
+            width_scale,
+            height_scale,
+            half_width: width_64 / 2.,
+            half_height: height_64 / 2.,
+            offset_x: settings.offset_x,
+            offset_y: settings.offset_y,
+            start_x: region.x0,
+            end_x: region.x1,
+            end_y: region.y1,
+            x: region.x0,
+            y: region.y0,
+            yi: 0.,
+            transformed: (settings.view_transform != ViewTransform::Linear)
+                .then(|| settings.clone()),
+        };
+        pixels.yi = pixels.row_coord(region.y0);
+        pixels
+    }
+
+    /// Yield the coordinates of the linear view, before `view_transform` moves them, for the renderer to offset
+    /// each sample within its pixel first.
+    fn linear(self) -> ViewPixels {
+        ViewPixels {
+            transformed: None,
+            ..self
+        }
+    }
+
+    fn row_coord(&self, y: u32) -> f32 {
+        (y as f32 - self.half_height) * self.height_scale + self.offset_y // Y coord
+    }
+}
+
+impl Iterator for ViewPixels {
+    type Item = (u32, u32, Complex<f32>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.y >= self.end_y || self.start_x >= self.end_x {
+            return None;
+        }
+        let (x, y, yi) = (self.x, self.y, self.yi);
+        let xi = (x as f32 - self.half_width) * self.width_scale + self.offset_x; // X coord
+
+        // Advance along the row, wrapping to the start of the next
+        self.x += 1;
+        if self.x == self.end_x {
+            self.x = self.start_x;
+            self.y += 1;
+            self.yi = self.row_coord(self.y);
+        }
+        let c = Complex::new(xi, yi);
+        Some((
+            x,
+            y,
+            self.transformed
+                .as_ref()
+                .map_or(c, |settings| view_point(settings, c)),
+        ))
+    }
+}
+
+/// Iteration count of a single point, or `NaN` if its coordinate or orbit became non-finite. Iterates for at most
+/// `max_iterations` steps, normally `settings.max_iterations`; with fewer, a point still bounded at the end is
+/// counted as interior all the same, so the counts colour alike whatever limit each point had.
+#[inline(always)]
+pub(crate) fn count_point(
+    point: Complex<f32>,
+    settings: &MandelbrotSettings,
+    max_iterations: u32,
+) -> f32 {
+    count_point_in(point, settings, max_iterations)
+}
+
+/// `count_point` iterated in f64, for views too deep for f32 (see `MandelbrotSettings::needs_f64`).
+#[inline(always)]
+pub(crate) fn count_point_f64(
+    point: Complex<f64>,
+    settings: &MandelbrotSettings,
+    max_iterations: u32,
+) -> f32 {
+    count_point_in(point, settings, max_iterations)
+}
+
+/// The float types points are iterated in. Settings are all f32, so `From<f32>` brings them in, and the counts
+/// come out as f32 whichever was used.
+trait Real: Float + From<f32> + AsPrimitive<f32> {}
+
+impl<T: Float + From<f32> + AsPrimitive<f32>> Real for T {}
+
+/// Whether both parts of `z` are finite, as `Complex::is_finite`, which only the float types' core trait gives.
+#[inline(always)]
+fn is_finite<T: Real>(z: Complex<T>) -> bool {
+    z.re.is_finite() && z.im.is_finite()
+}
+
+/// Widen an f32 complex number to the type a point is iterated in.
+#[inline(always)]
+fn widen<T: Real>(z: Complex<f32>) -> Complex<T> {
+    Complex::new(z.re.into(), z.im.into())
+}
+
+#[inline(always)]
+fn count_point_in<T: Real>(
+    point: Complex<T>,
+    settings: &MandelbrotSettings,
+    max_iterations: u32,
+) -> f32 {
+    // Iterate the fractal's function, e.g. z = z^2 + c for the mandelbrot. The set fractals take c from the point,
+    // starting z at `settings.z0` (see there for the off-by-one when starting at c); Julia sets do the reverse
+    let z0 = settings.z0.map_or(point, widen);
+    match settings.fractal {
+        FractalKind::Mandelbrot => {
+            count_point_with(z0, point, settings, max_iterations, |z, c| z * z + c)
+        }
+        FractalKind::Tricorn => count_point_with(z0, point, settings, max_iterations, tricorn_step),
+        FractalKind::Julia => {
+            let c = widen(settings.julia_c);
+            count_point_with(point, c, settings, max_iterations, |z, c| z * z + c)
+        }
+    }
+}
+
+#[inline(always)]
+fn count_point_with<T: Real>(
+    z0: Complex<T>,
+    c: Complex<T>,
+    settings: &MandelbrotSettings,
+    max_iterations: u32,
+    step: impl Fn(Complex<T>, Complex<T>) -> Complex<T> + Copy,
+) -> f32 {
+    // Only the orbit radius colouring needs the largest |z| along the way, so only it pays to track it, each step
+    // keeping the larger of it and the new |z|²
+    let largest = Cell::new(z0.norm_sqr());
+    let (i, z) = if settings.interior_mode == InteriorMode::OrbitRadius {
+        escape_time_with_bailout(z0, c, settings, max_iterations, |z, c| {
+            let z = step(z, c);
+            largest.set(largest.get().max(z.norm_sqr()));
+            z
+        })
+    } else {
+        escape_time_with_bailout(z0, c, settings, max_iterations, step)
+    };
+
+    // A non-finite value means the view has broken down (e.g. zoom overflow)
+    if !(is_finite(z0) && is_finite(c) && is_finite(z)) {
+        return f32::NAN;
+    }
+    if i >= max_iterations {
+        return match settings.interior_mode {
+            InteriorMode::Period => (settings.max_iterations + orbit_period(z, c, step)) as f32,
+            InteriorMode::OrbitRadius => {
+                // Carried below the next whole count, so the interior stays one band for contours
+                let radius: f32 = (largest.get().sqrt() / ORBIT_RADIUS_SCALE.into()).as_();
+                settings.max_iterations as f32 + radius.min(1. - f32::EPSILON)
+            }
+            _ => settings.max_iterations as f32,
+        };
+    }
+    let count = match settings.colour_mode {
+        ColourMode::Smooth => smooth_count(i, z, c, settings, step),
+        ColourMode::Potential => potential_level(i, z),
+        ColourMode::Bands => i as f32,
+    };
+    // Past 2^24 f32 can't hold every whole count, and the nearest to an escape just short of the limit can be
+    // the limit itself, which would colour the pixel as interior. Such counts stay just below it
+    count.min((settings.max_iterations as f32).next_down())
+}
+
+/// Period of the cycle an interior orbit has settled into by `z`, found by stepping until it comes back within
+/// `PERIOD_TOLERANCE`. 0 if it doesn't within `MAX_PERIOD` steps, such as near a bulb's edge where the orbit
+/// converges too slowly to have settled by the iteration limit.
+#[inline(always)]
+fn orbit_period<T: Real>(
+    start: Complex<T>,
+    c: Complex<T>,
+    step: impl Fn(Complex<T>, Complex<T>) -> Complex<T>,
+) -> u32 {
+    let tolerance_sqr: T = (PERIOD_TOLERANCE * PERIOD_TOLERANCE).into();
+    let mut z = start;
+    for period in 1..=MAX_PERIOD {
+        z = step(z, c);
+        if (z - start).norm_sqr() < tolerance_sqr {
+            return period;
+        }
+    }
+    0
+}
+
+/// Most extra iterations `smooth_count` may average over. Each squares |z|, so a few more would overflow f32.
+pub const MAX_SMOOTH_EXTRA_ITERATIONS: u32 = 4;
+
+/// Fractional escape count for a point that escaped after `i` steps at `z`, so colours blend between bands.
+///
+/// Far outside the bailout each step roughly squares |z|, so `i - log2(ln|z| / ln R)`, with R the `bailout`, changes
+/// continuously across band edges. For the circle of radius 2 it lies between `i - 1.2` and `i`, keeping escaped
+/// points below `max_iterations`, and a larger radius brings it closer to the exterior potential's level.
+///
+/// The other escape shapes keep it continuous too, since on a band edge the points either side escape at the
+/// same z, one step apart. But the |z| they escape at varies round the shape, up to √2 R at its corners, so
+/// equal colours no longer lie on equipotentials and the gradient takes on the shape's corners. The extra steps
+/// below carry z far enough out to wash most of that away, more so at a higher bailout. Just past the
+/// bailout the + c term still bends the orbit, so this estimate wobbles a little as the view moves, which shows as
+/// shimmer in zoom videos. Taking `settings.smooth_extra_iterations` more steps and averaging the estimate after
+/// each one converges on the far-field value and settles the wobble.
+#[inline(always)]
+fn smooth_count<T: Real>(
+    i: u32,
+    mut z: Complex<T>,
+    c: Complex<T>,
+    settings: &MandelbrotSettings,
+    step: impl Fn(Complex<T>, Complex<T>) -> Complex<T>,
+) -> f32 {
+    let ln_radius = settings.bailout.ln();
+    // |z| is past the bailout, so its log is as good in f32 whatever z was iterated in
+    let estimate = |steps: u32, z: Complex<T>| {
+        let norm: f32 = z.norm().as_();
+        steps as f32 - (norm.ln() / ln_radius).log2()
+    };
+    let mut total = estimate(i, z);
+    let mut estimates = 1;
+    for extra in 1..=settings
+        .smooth_extra_iterations
+        .min(MAX_SMOOTH_EXTRA_ITERATIONS)
+    {
+        z = step(z, c);
+        let next = estimate(i + extra, z);
+        if !next.is_finite() {
+            break; // |z| overflowed after all, so keep the estimates so far
+        }
+        total += next;
+        estimates += 1;
+    }
+    (total / estimates as f32).clamp(0., i as f32)
+}
+
+/// Level of the exterior potential `G(c) = ln|z_n| / 2^n` of a point that escaped past `POTENTIAL_ESCAPE_RADIUS`
+/// after `i` iterations, as `-log2(G / ln R)`. Each unit halves the potential, so the levels line up with the
+/// iteration counts, but they vary continuously across the band edges and curves of equal level are the
+/// equipotentials around the set. Worked out as `i - log2(ln|z| / ln R)` so 2^n can't overflow. Like smooth
+/// counts, it's clamped between 0 and `i`, below `max_iterations`.
+#[inline(always)]
+fn potential_level<T: Real>(i: u32, z: Complex<T>) -> f32 {
+    let norm: f32 = z.norm().as_();
+    let level = i as f32 - (norm.ln() / POTENTIAL_ESCAPE_RADIUS.ln()).log2();
+    level.clamp(0., i as f32)
+}
+
+/// Calculate the iteration count of every pixel, without colouring.
+///
+/// Counts are in row-major order (`counts[y * width + x]`), so they can be analysed or coloured independently of
+/// the window. Points inside the set get `max_iterations`, plus their orbit's period with `InteriorMode::Period`
+/// (nothing extra if none was found), or plus a fraction from `InteriorMode::OrbitRadius`'s largest |z| / 2.
+/// Pixels whose coordinates or orbit became NaN/inf get `NaN`, so degenerate views can be told apart from real
+/// results.
+///
+/// When supersampling there's one count per sample, at the `settings.supersampled()` resolution returned
+/// alongside them, with each sample placed by `settings.sample_pattern`.
+pub fn render_counts(settings: &MandelbrotSettings) -> (Vec<f32>, u32, u32) {
+    let grid = settings.supersampled();
+    let counts = count_samples(
+        settings,
+        Region::full(settings),
+        |_, _, _| None,
+        &NOT_CANCELLED,
+    );
+    (counts, grid.width, grid.height)
+}
+
+/// Like `render_counts`, for just the pixels of `region`. The counts are in row-major order within the
+/// region, and match the full render's counts for the same pixels exactly.
+pub fn render_counts_region(settings: &MandelbrotSettings, region: Region) -> Vec<f32> {
+    count_samples(settings, region, |_, _, _| None, &NOT_CANCELLED)
+}
+
+/// Like `render_counts`, but for a view panned by whole pixels from `previous`, whose counts are
+/// `previous_counts`. Pixel (x, y) of the new view is pixel (x + dx, y + dy) of the old one, so the overlap is
+/// copied across and only the newly exposed strips are iterated. The pan is in output pixels, even when
+/// supersampling, and everything but the offset should be the same in both views.
+///
+/// The result is exactly what `render_counts` gives for the new view. f32 rounding of the offset can put a copied
+/// pixel's coordinate a bit away from where the new view has it, so those pixels are iterated again too.
+pub fn render_counts_panned(
+    settings: &MandelbrotSettings,
+    previous: &MandelbrotSettings,
+    previous_counts: &[f32],
+    dx: i32,
+    dy: i32,
+) -> Vec<f32> {
+    let grid = settings.supersampled(); // The counts are at the supersampled resolution
+    let previous_grid = previous.supersampled();
+    let (width, height) = (grid.width as i64, grid.height as i64);
+    let scale = settings.supersample as i64;
+    let offsets = sample_offsets(settings);
+    let n = settings.supersample;
+    if settings.view_transform != ViewTransform::Linear
+        || settings.importance.is_some()
+        || grid.needs_f64()
+        || settings.precision != Precision::Auto
+    {
+        // Moving the centre doesn't just shift a transformed view, and the importance map stays put as the view
+        // moves under it. Samples iterated in f64 aren't at the f32 points the copies are checked against
+        return render_counts(settings).0;
+    }
+    count_samples(
+        settings,
+        Region::full(settings),
+        |x, y, point| {
+            // Panning by whole output pixels keeps every sample at the same place within its pixel
+            let source_x = x as i64 + dx as i64 * scale;
+            let source_y = y as i64 + dy as i64 * scale;
+            if !((0..width).contains(&source_x) && (0..height).contains(&source_y)) {
+                return None;
+            }
+            // Only reuse the count if the old view iterated the very same point, as rounding can shift it
+            let offset = offsets[((y % n) * n + x % n) as usize];
+            let source = linear_point(&previous_grid, source_x as f32, source_y as f32) + offset;
+            (source == point).then(|| previous_counts[(source_y * width + source_x) as usize])
+        },
+        &NOT_CANCELLED,
+    )
+}
+
+/// For renders that can't be cancelled.
+static NOT_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Count every sample of `region` (in output pixels) at the `settings.supersampled()` resolution, unless `reuse`
+/// already knows the count of the sample at (x, y), which is at `point` in the linear view. Rows not yet started
+/// once `cancel` is set are skipped and left at 0, so the result is only meaningful if `cancel` is still clear
+/// afterwards. Samples are iterated in f32 or f64 as `settings.precision` picks, see `Precision`.
+fn count_samples(
+    settings: &MandelbrotSettings,
+    region: Region,
+    reuse: impl Fn(u32, u32, Complex<f32>) -> Option<f32> + Sync,
+    cancel: &AtomicBool,
+) -> Vec<f32> {
+    let grid = settings.supersampled();
+    let offsets = sample_offsets(settings);
+    let n = settings.supersample;
+    let region = region.scaled(n);
+    let linear = settings.view_transform == ViewTransform::Linear;
+    // Past the f32 limit an f32 pass is just noise, so even the hybrid iterates everything in f64
+    let all_f64 = linear && (settings.precision == Precision::Double || grid.needs_f64());
+    let hybrid = linear && !all_f64 && settings.precision == Precision::Hybrid;
+    let columns = region.width() as usize;
+    let mut counts = vec![0.; columns * region.height() as usize];
+
+    let limit_at = |x: u32, y: u32| match &settings.importance {
+        // Looked up at the sample's place in the image, so previews use the same map
+        Some(map) => map.iterations_at(
+            (x as f32 + 0.5) / grid.width as f32,
+            (y as f32 + 0.5) / grid.height as f32,
+            grid.max_iterations,
+        ),
+        None => grid.max_iterations,
+    };
+    // The f32 point may have rounded off where the sample really is, so start again from the pixel
+    let count_f64 = |x: u32, y: u32| {
+        let offset = offsets[((y % n) * n + x % n) as usize];
+        count_point_f64(
+            linear_point_f64(&grid, x, y) + widen(offset),
+            &grid,
+            limit_at(x, y),
+        )
+    };
+
+    // Iterate over the rows in parallel
+    counts
+        .par_chunks_mut(columns.max(1)) // max(1) because chunks can't be empty, even if the image is
+        .enumerate() // Enumerate the rows in parallel
+        .for_each(|(row_index, row)| {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let y = region.y0 + row_index as u32;
+            let row_region = Region {
+                y0: y,
+                y1: y + 1,
+                ..region
+            };
+            for (sample, (x, y, c)) in row
+                .iter_mut()
+                .zip(ViewPixels::region(&grid, row_region).linear())
+            {
+                let offset = offsets[((y % n) * n + x % n) as usize]; // Which sample of its pixel this is
+                let point = c + offset;
+                *sample = reuse(x, y, point).unwrap_or_else(|| match all_f64 {
+                    true => count_f64(x, y),
+                    false => count_point(view_point(&grid, point), &grid, limit_at(x, y)),
+                });
+            }
+        });
+
+    if hybrid {
+        // Go over the samples that need it again in f64, leaving the f32 counts of the rest
+        let precise = precise_samples(&counts, region, settings);
+        counts
+            .par_chunks_mut(columns.max(1))
+            .zip(precise.par_chunks(columns.max(1)))
+            .enumerate()
+            .for_each(|(row_index, (row, precise))| {
+                if cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+                let y = region.y0 + row_index as u32;
+                for (column, (sample, _)) in (row.iter_mut().zip(precise))
+                    .enumerate()
+                    .filter(|(_, (_, &precise))| precise)
+                {
+                    let x = region.x0 + column as u32;
+                    *sample = count_f64(x, y);
+                }
+            });
+    }
+
+    counts
+}
+
+/// Which samples of `counts`, covering `region` of the supersampled grid, `Precision::Hybrid` iterates again in
+/// f64: those within `settings.f64_region`, and those on the set's boundary, where the f32 pass found a
+/// neighbour of another `PixelClass`. That's where rounding decides whether an orbit escapes, while away from
+/// the boundary the counts change slowly and f32 gets them right. Neighbours outside the region aren't seen, so
+/// a tile's edges can come out slightly differently from the full render.
+fn precise_samples(counts: &[f32], region: Region, settings: &MandelbrotSettings) -> Vec<bool> {
+    let (columns, rows) = (region.width() as usize, region.height() as usize);
+    let class =
+        |column: usize, row: usize| PixelClass::of(counts[row * columns + column], settings);
+    let chosen = settings
+        .f64_region
+        .map(|chosen| chosen.scaled(settings.supersample));
+    let mut precise = vec![false; counts.len()];
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (region.x0 + column as u32, region.y0 + row as u32);
+            let in_chosen = chosen.is_some_and(|chosen| {
+                (chosen.x0..chosen.x1).contains(&x) && (chosen.y0..chosen.y1).contains(&y)
+            });
+            let here = class(column, row);
+            let on_boundary = (row.saturating_sub(1)..=(row + 1).min(rows - 1)).any(|r| {
+                (column.saturating_sub(1)..=(column + 1).min(columns - 1))
+                    .any(|c| class(c, r) != here)
+            });
+            precise[row * columns + column] = in_chosen || on_boundary;
+        }
+    }
+    precise
+}
+
+/// How far each sample of a pixel is moved from its regular grid position by `settings.sample_pattern`, as a
+/// complex offset. Indexed like `SamplePattern::positions`. Without supersampling there's one, unmoved, sample.
+fn sample_offsets(settings: &MandelbrotSettings) -> Vec<Complex<f32>> {
+    let n = settings.supersample;
+    if n <= 1 {
+        return vec![Complex::new(0., 0.)]; // Moving the only sample would just shift the image
+    }
+    let [width_scale, height_scale] = settings.supersampled().pixel_scale();
+    (0..n * n)
+        .zip(settings.sample_pattern.positions(n, settings.sample_seed))
+        .map(|(k, [px, py])| {
+            // Grid sample (i, j) of the block sits at (i / n, j / n) of the pixel
+            let (i, j) = ((k % n) as f32, (k / n) as f32);
+            Complex::new(
+                (px * n as f32 - i) * width_scale,
+                (py * n as f32 - j) * height_scale,
+            )
+        })
+        .collect()
+}
+
+/// Find the escaped pixel with the highest iteration count, i.e. the most detailed boundary point in view.
+/// Ties go to the first pixel in row-major order. Returns `None` if nothing escaped.
+pub fn deepest_escaped_pixel(
+    counts: &[f32],
+    width: u32,
+    max_iterations: u32,
+) -> Option<(u32, u32)> {
+    let mut deepest: Option<(usize, f32)> = None;
+    for (index, &count) in counts.iter().enumerate() {
+        // NaN fails both comparisons, so degenerate pixels are skipped too
+        if count < max_iterations as f32 && deepest.is_none_or(|(_, best)| count > best) {
+            deepest = Some((index, count));
+        }
+    }
+    deepest.map(|(index, _)| (index as u32 % width, index as u32 / width))
+}
+
+/// The built-in colouring of a single iteration count: the palette if there is one, otherwise grayscale.
+#[inline(always)]
+pub fn builtin_colour(count: f32, settings: &MandelbrotSettings) -> Rgba<u8> {
+    match PixelClass::of(count, settings) {
+        PixelClass::Degenerate => Rgba(settings.degenerate_colour),
+        PixelClass::Interior => {
+            let mut colour = match orbit_radius_position(count, settings) {
+                Some(t) => ramp_colour(t, settings),
+                None => {
+                    period_colour(count, settings).unwrap_or_else(|| flat_interior_colour(settings))
+                }
+            };
+            if settings.transparency == Transparency::Interior {
+                colour[3] = 0;
+            }
+            Rgba(colour)
+        }
+        PixelClass::Escaped if settings.transparency == Transparency::Exterior => {
+            // Only the set shows, so take its colour and let the edge blend fade it in. Supersampled edges then
+            // average to the set's colour at partial alpha, which composites cleanly
+            let mut colour = flat_interior_colour(settings);
+            let weight = edge_blend_weight(count, settings).unwrap_or(0.);
+            colour[3] = (colour[3] as f32 * weight).round() as u8;
+            Rgba(colour)
+        }
+        PixelClass::Escaped => {
+            let colour = ramp_colour(colour_position(count, settings), settings);
+            match edge_blend_weight(count, settings) {
+                Some(weight) => Rgba(blend_colours(
+                    colour,
+                    flat_interior_colour(settings),
+                    weight,
+                )),
+                None => Rgba(colour),
+            }
+        }
+    }
+}
+
+/// Like `builtin_colour`, at 16 bits per channel. Palette colours are interpolated without rounding to
+/// 8 bits first, so gradients between entries stay smooth.
+#[inline(always)]
+pub fn builtin_colour_16(count: f32, settings: &MandelbrotSettings) -> Rgba<u16> {
+    let widen = |colour: [f32; 4]| Rgba(colour.map(|channel| (channel * 257.).round() as u16)); // 255 * 257 = 65535
+    let fixed = |colour: [u8; 4]| colour.map(|channel| channel as f32);
+    let interior = || match settings.interior_colour {
+        Some(colour) => fixed(colour),
+        None => ramp_colour_wide(1., settings),
+    };
+    match PixelClass::of(count, settings) {
+        PixelClass::Degenerate => widen(fixed(settings.degenerate_colour)),
+        PixelClass::Interior => {
+            let mut colour = match (
+                orbit_radius_position(count, settings),
+                period_colour(count, settings),
+            ) {
+                (Some(t), _) => ramp_colour_wide(t, settings),
+                (None, Some(colour)) => fixed(colour),
+                (None, None) => interior(),
+            };
+            if settings.transparency == Transparency::Interior {
+                colour[3] = 0.;
+            }
+            widen(colour)
+        }
+        PixelClass::Escaped if settings.transparency == Transparency::Exterior => {
+            let mut colour = interior();
+            colour[3] *= edge_blend_weight(count, settings).unwrap_or(0.);
+            widen(colour)
+        }
+        PixelClass::Escaped => {
+            let colour = ramp_colour_wide(colour_position(count, settings), settings);
+            match edge_blend_weight(count, settings) {
+                Some(weight) => {
+                    let interior = interior();
+                    widen(std::array::from_fn(|i| {
+                        colour[i] * (1. - weight) + interior[i] * weight
+                    }))
+                }
+                None => widen(colour),
+            }
+        }
+    }
+}
+
+/// The colour at position `t` along the ramp, mixing in `blend_palette` if there is one.
+#[inline(always)]
+fn ramp_colour(t: f32, settings: &MandelbrotSettings) -> [u8; 4] {
+    let colour = match &settings.palette {
+        Some(palette) => palette.sample(t),
+        None => {
+            let lum = (t * 255.0) as u8;
+            [lum, lum, lum, 255]
+        }
+    };
+    match &settings.blend_palette {
+        Some(other) => blend_colours(colour, other.sample(t), settings.palette_blend),
+        None => colour,
+    }
+}
+
+/// Like `ramp_colour`, with unrounded channels from 0 to 255 for 16 bit output.
+#[inline(always)]
+fn ramp_colour_wide(t: f32, settings: &MandelbrotSettings) -> [f32; 4] {
+    let colour = match &settings.palette {
+        Some(palette) => palette.sample_wide(t),
+        None => [t * 255., t * 255., t * 255., 255.],
+    };
+    match &settings.blend_palette {
+        Some(other) => {
+            let other = other.sample_wide(t);
+            let blend = settings.palette_blend;
+            std::array::from_fn(|i| colour[i] * (1. - blend) + other[i] * blend)
+        }
+        None => colour,
+    }
+}
+
+/// Colour of the interior when it isn't coloured by period: `interior_colour`, or else the end of the ramp,
+/// whatever the lift, offset or period do to the exterior.
+#[inline(always)]
+fn flat_interior_colour(settings: &MandelbrotSettings) -> [u8; 4] {
+    settings
+        .interior_colour
+        .unwrap_or_else(|| ramp_colour(1., settings))
+}
+
+/// How far `edge_blend` pulls an escaped pixel towards the interior's colour, from its count. `None` when it's
+/// off or the pixel is too far out to be touched.
+#[inline(always)]
+fn edge_blend_weight(count: f32, settings: &MandelbrotSettings) -> Option<f32> {
+    if !settings.edge_blend {
+        return None;
+    }
+    // Pixels next to the set escape late, and the later a pixel escapes the more of its area a supersampled
+    // render finds inside the set. On the log scale that share grows from nothing at about halfway to the limit
+    // to roughly half right at it, so ease from 0 to EDGE_BLEND_STRENGTH across that range
+    let position = count.ln_1p() / (settings.max_iterations as f32).ln_1p();
+    let x = ((position - EDGE_BLEND_START) / (1. - EDGE_BLEND_START)).clamp(0., 1.);
+    let weight = x * x * (3. - 2. * x) * EDGE_BLEND_STRENGTH; // Smoothstep
+    (weight > 0.).then_some(weight)
+}
+
+/// Colour of an interior pixel by its period, when `InteriorMode::Period` found one. Interior counts carry the
+/// period on top of `max_iterations`, see `render_counts`.
+fn period_colour(count: f32, settings: &MandelbrotSettings) -> Option<[u8; 4]> {
+    let period = (count - settings.max_iterations as f32) as usize;
+    (settings.interior_mode == InteriorMode::Period && period > 0)
+        .then(|| PERIOD_COLOURS[(period - 1) % PERIOD_COLOURS.len()])
+}
+
+/// Where an interior pixel falls on the colour ramp by its orbit's largest |z|, with `InteriorMode::OrbitRadius`.
+/// Interior counts carry it as a fraction on top of `max_iterations`, see `render_counts`. Past about 2^23
+/// iterations f32 can't hold the fraction, and the interior goes back to the start of the ramp.
+fn orbit_radius_position(count: f32, settings: &MandelbrotSettings) -> Option<f32> {
+    (settings.interior_mode == InteriorMode::OrbitRadius)
+        .then(|| (count - settings.max_iterations as f32).clamp(0., 1.))
+}
+
+/// Which of the three kinds of pixel an iteration count is, each coloured its own way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelClass {
+    Escaped,    // Left the bailout radius before the limit. Coloured along the ramp by its count
+    Interior, // Still bounded at `max_iterations`, so taken to be in the set. `interior_colour`, or the ramp's end
+    Degenerate, // The coordinates or iteration became NaN/inf, so the count means nothing. `degenerate_colour`
+}
+
+impl PixelClass {
+    pub fn of(count: f32, settings: &MandelbrotSettings) -> PixelClass {
+        if !count.is_finite() {
+            PixelClass::Degenerate
+        } else if count >= settings.max_iterations as f32 {
+            PixelClass::Interior
+        } else {
+            PixelClass::Escaped
+        }
+    }
+}
+
+/// Where an escaped count falls on the colour ramp, from 0 up to but never reaching 1, which is left to the
+/// interior. Without an `interior_colour` the set is the ramp's end (white in grayscale), so the nearest escaped
+/// pixels come close to it without matching it.
+#[inline(always)]
+fn colour_position(count: f32, settings: &MandelbrotSettings) -> f32 {
+    // Rounding to f32 can land on 1, which is kept for the interior
+    (ramp_position(count, settings) as f32).min(1. - f32::EPSILON)
+}
+
+/// `colour_position` before rounding to f32. Worked in f64: past a million or so iterations, neighbouring counts
+/// are closer together near the end of the ramp than f32 can tell apart, and the gradient would go back to bands.
+fn ramp_position(count: f32, settings: &MandelbrotSettings) -> f64 {
+    // Scale to 0..1, logarithmically if asked so each doubling of the count moves the same distance along the
+    // ramp, then correct gamma. With a colour period the ramp repeats every `period` iterations instead of
+    // spanning the iteration limit, so raising the limit doesn't change the colours already showing
+    let count = count as f64;
+    let span = settings
+        .colour_period
+        .map_or(settings.max_iterations as f64, |period| period as f64);
+    let linear = match (settings.log_colour, settings.colour_period) {
+        (true, None) => count.ln_1p() / span.ln_1p(),
+        (false, None) => count / span,
+        (true, Some(_)) => (count.ln_1p() / span.ln_1p()).fract(),
+        (false, Some(_)) => (count / span).fract(), // Counts are never negative, so this wraps into 0..1
+    };
+    let mut t = linear.powf(settings.gamma as f64).min(1. - f64::EPSILON);
+
+    // Brighten or darken the points that escaped almost immediately. Full strength at 1 iteration or fewer,
+    // falling off quickly so the rest of the exterior keeps its colouring. Stay below the interior's position,
+    // which a strong lift at a low iteration limit would otherwise reach.
+    if settings.exterior_lift != 0. {
+        let weight = (-(count - 1.) / EXTERIOR_FALLOFF as f64).exp().min(1.);
+        t = (t * (1. + settings.exterior_lift as f64 * weight)).clamp(0., 1. - f64::EPSILON);
+    }
+
+    // Rotate the colours, wrapping past the end of the ramp back to its start. A palette whose ends match
+    // rotates seamlessly; others show a seam where the ends meet
+    if settings.colour_offset != 0. {
+        t = (t + settings.colour_offset as f64)
+            .rem_euclid(1.)
+            .min(1. - f64::EPSILON); // rem_euclid can round up to 1
+    }
+    t
+}
+
+/// Mix `a` and `b` channel by channel, weighting `b` by `blend`. 0 gives exactly `a` and 1 exactly `b`.
+#[inline(always)]
+fn blend_colours(a: [u8; 4], b: [u8; 4], blend: f32) -> [u8; 4] {
+    let mix = |a: u8, b: u8| (a as f32 * (1. - blend) + b as f32 * blend).round() as u8;
+    [
+        mix(a[0], b[0]),
+        mix(a[1], b[1]),
+        mix(a[2], b[2]),
+        mix(a[3], b[3]),
+    ]
+}
+
+/// Colour a buffer of iteration counts from `render_counts`.
+pub fn colorize(counts: &[f32], settings: &MandelbrotSettings) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    colorize_with(counts, settings, builtin_colour)
+}
+
+/// Colour a buffer of iteration counts with a custom function of each pixel's count (`NaN` for degenerate
+/// pixels) and the settings. It's generic rather than boxed, so the call can be inlined into the loop.
+pub fn colorize_with<P: OutputPixel>(
+    counts: &[f32],
+    settings: &MandelbrotSettings,
+    colorer: impl Fn(f32, &MandelbrotSettings) -> P + Sync,
+) -> Image<P> {
+    let mut img = ImageBuffer::new(settings.width, settings.height);
+
+    img.as_mut()
+        .par_chunks_mut(P::CHANNEL_COUNT as usize) // each pixel has 4 channels
+        .zip(counts.par_iter())
+        .for_each(|(pixel, &count)| {
+            pixel.copy_from_slice(colorer(count, settings).channels()); // set pixel colour
+        });
+
+    if settings.contours {
+        draw_contours(&mut img, counts, settings.width);
+    }
+
+    img
+}
+
+/// Darken every pixel whose iteration count differs from its right or lower neighbour's, tracing the
+/// boundaries between bands like the contour lines of a map. Degenerate pixels are left alone.
+fn draw_contours<P: OutputPixel>(img: &mut Image<P>, counts: &[f32], width: u32) {
+    let columns = width as usize;
+    img.as_mut()
+        .par_chunks_mut(P::CHANNEL_COUNT as usize)
+        .enumerate()
+        .for_each(|(index, pixel)| {
+            let count = counts[index];
+            let right = (index % columns + 1 < columns).then(|| counts[index + 1]);
+            let below = counts.get(index + columns).copied();
+            // Compare whole bands, so smooth counts only get lines where the band changes
+            let differs = |neighbour: Option<f32>| {
+                neighbour.is_some_and(|n| n.is_finite() && n.floor() != count.floor())
+            };
+            if count.is_finite() && (differs(right) || differs(below)) {
+                P::from_slice_mut(pixel).shade(CONTOUR_SHADE);
+            }
+        });
+}
+
+/// Render an image, also returning the iteration counts it was coloured from.
+/// When supersampling, the counts are at the `settings.supersampled()` resolution rather than the image's.
+pub fn render_with_counts(
+    settings: &MandelbrotSettings,
+) -> (Vec<f32>, ImageBuffer<Rgba<u8>, Vec<u8>>) {
+    let (counts, _, _) = render_counts(settings);
+    let img = colorize_supersampled(&counts, settings, builtin_colour);
+    (counts, img)
+}
+
+/// Like `render_with_counts`, but giving up with `None` soon after `cancel` is set, from another thread.
+/// Rows already being iterated finish first, so this takes at most about a row's time per thread to notice.
+pub fn render_with_counts_cancellable(
+    settings: &MandelbrotSettings,
+    cancel: &AtomicBool,
+) -> Option<(Vec<f32>, Image<Rgba<u8>>)> {
+    let counts = count_samples(settings, Region::full(settings), |_, _, _| None, cancel);
+    if cancel.load(Ordering::Relaxed) {
+        return None;
+    }
+    let img = colorize_supersampled(&counts, settings, builtin_colour);
+    Some((counts, img))
+}
+
+/// Render an image coloured by `colorer` instead of the built-in palette or grayscale. See `colorize_with`.
+/// When supersampling, `colorer` sees the `settings.supersampled()` settings the counts were iterated with.
+pub fn render_with_colorer(
+    settings: &MandelbrotSettings,
+    colorer: impl Fn(f32, &MandelbrotSettings) -> Rgba<u8> + Sync,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (counts, _, _) = render_counts(settings);
+    let img = colorize_with(&counts, &settings.supersampled(), colorer);
+    downscale(img, settings)
+}
+
+/// Like `render_with_counts`, for a view panned by `dx`, `dy` output pixels since `previous` was rendered.
+/// See `render_counts_panned`.
+pub fn render_with_counts_panned(
+    settings: &MandelbrotSettings,
+    previous: &MandelbrotSettings,
+    previous_counts: &[f32],
+    dx: i32,
+    dy: i32,
+) -> (Vec<f32>, ImageBuffer<Rgba<u8>, Vec<u8>>) {
+    let counts = render_counts_panned(settings, previous, previous_counts, dx, dy);
+    let img = colorize_supersampled(&counts, settings, builtin_colour);
+    (counts, img)
+}
+
+/// Colour the counts from `render_counts` again, as `render_with_counts` would have, without iterating. Supersampled
+/// counts are shrunk to the output size.
+pub fn recolorize(counts: &[f32], settings: &MandelbrotSettings) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    colorize_supersampled(counts, settings, builtin_colour)
+}
+
+/// Colour counts at the supersampled resolution, then shrink them to the output size.
+fn colorize_supersampled<P: OutputPixel>(
+    counts: &[f32],
+    settings: &MandelbrotSettings,
+    colorer: impl Fn(f32, &MandelbrotSettings) -> P + Sync,
+) -> Image<P> {
+    downscale(
+        colorize_with(counts, &settings.supersampled(), colorer),
+        settings,
+    )
+}
+
+/// Shrink an image rendered at the `settings.supersampled()` resolution to the output size.
+fn downscale<P: OutputPixel>(img: Image<P>, settings: &MandelbrotSettings) -> Image<P> {
+    if settings.supersample > 1 {
+        settings
+            .downscale_filter
+            .resize(&img, settings.width, settings.height)
+    } else {
+        img
+    }
+}
+
+/// Render just `region` of the view, as an image the size of the region. Tiling a view with regions reproduces
+/// `render` exactly, except that contour lines and non-box downscale filters can't see across tile edges.
+pub fn render_region(
+    settings: &MandelbrotSettings,
+    region: Region,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+    render_tile(settings, region, builtin_colour)
+}
+
+/// Like `render_region`, at 16 bits per channel.
+pub fn render_region_16(
+    settings: &MandelbrotSettings,
+    region: Region,
+) -> Result<ImageBuffer<Rgba<u16>, Vec<u16>>, String> {
+    render_tile(settings, region, builtin_colour_16)
+}
+
+fn render_tile<P: OutputPixel>(
+    settings: &MandelbrotSettings,
+    region: Region,
+    colorer: impl Fn(f32, &MandelbrotSettings) -> P + Sync,
+) -> Result<Image<P>, String> {
+    region.check(settings)?;
+    let counts = render_counts_region(settings, region);
+    let tile = MandelbrotSettings {
+        width: region.width(),
+        height: region.height(),
+        ..settings.clone()
+    };
+    Ok(colorize_supersampled(&counts, &tile, colorer)) // Colouring only looks at the size, not the coordinates
+}
+
+/// Render an image of the view described by `settings`.
+pub fn render(settings: &MandelbrotSettings) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    render_with_counts(settings).1
+}
+
+/// Like `render`, as a plain buffer of `settings.width * settings.height` pixels, row by row with 4 bytes
+/// (red, green, blue, alpha) each. For callers that don't use the `image` crate, such as JS via wasm.
+pub fn render_rgba(settings: &MandelbrotSettings) -> Vec<u8> {
+    render(settings).into_raw()
+}
+
+/// Like `render`, at 16 bits per channel, for saving images with smoother gradients than 8 bits allow.
+pub fn render_16(settings: &MandelbrotSettings) -> ImageBuffer<Rgba<u16>, Vec<u16>> {
+    let (counts, _, _) = render_counts(settings);
+    colorize_supersampled(&counts, settings, builtin_colour_16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+    use std::sync::Arc;
+
+    /// Whether `a` and `b` are within `tolerance` of each other on both axes.
+    fn near(a: [f32; 2], b: [f32; 2], tolerance: f32) -> bool {
+        (a[0] - b[0]).abs() <= tolerance && (a[1] - b[1]).abs() <= tolerance
+    }
+
+    #[test]
+    fn the_centre_pixel_shows_the_offset() {
+        for (offset_x, offset_y) in [(0., 0.), (-1.25, 0.4), (-0.75, -0.35), (0.3, -1.1)] {
+            let settings = MandelbrotSettings {
+                width: 80,
+                height: 60,
+                zoom: 2.5,
+                offset_x,
+                offset_y,
+                ..MandelbrotSettings::default()
+            };
+            let centre = pixel_to_complex(&settings, 40., 30.);
+            assert_eq!(centre, Complex::new(offset_x, offset_y));
+            assert_eq!(complex_to_pixel(&settings, centre), [40., 30.]);
+        }
+    }
+
+    #[test]
+    fn the_image_corners_map_to_corners() {
+        let settings = MandelbrotSettings {
+            width: 80,
+            height: 60,
+            zoom: 2.,
+            offset_x: -1.5,
+            offset_y: -0.8,
+            ..MandelbrotSettings::default()
+        };
+        let (half_width, half_height) = (settings.world_width() / 2., settings.world_height() / 2.);
+        let expected = [
+            Complex::new(-1.5 - half_width, -0.8 - half_height),
+            Complex::new(-1.5 + half_width, -0.8 - half_height),
+            Complex::new(-1.5 + half_width, -0.8 + half_height),
+            Complex::new(-1.5 - half_width, -0.8 + half_height),
+        ];
+        let pixels = [[0., 0.], [80., 0.], [80., 60.], [0., 60.]];
+        for ((corner, expected), pixel) in settings.corners().into_iter().zip(expected).zip(pixels)
+        {
+            assert!(
+                (corner - expected).norm() < 1e-6,
+                "{} isn't {}",
+                corner,
+                expected
+            );
+            assert!(near(complex_to_pixel(&settings, corner), pixel, 1e-3));
+        }
+    }
+
+    #[test]
+    fn pixels_round_trip_at_deep_zooms() {
+        let settings = MandelbrotSettings {
+            width: 80,
+            height: 60,
+            zoom: 1e5,
+            offset_x: -0.7453,
+            offset_y: -0.1127,
+            ..MandelbrotSettings::default()
+        };
+        for y in (0..=60).step_by(10) {
+            for x in (0..=80).step_by(10) {
+                let pixel = [x as f32, y as f32];
+                let back =
+                    complex_to_pixel(&settings, pixel_to_complex(&settings, pixel[0], pixel[1]));
+                // A pixel is only a few f32 steps across this deep, so rounding moves it a fraction of one
+                assert!(
+                    near(back, pixel, 0.25),
+                    "{:?} came back as {:?}",
+                    pixel,
+                    back
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn view_pixels_match_pixel_to_complex_in_every_view() {
+        for view_transform in ViewTransform::ALL {
+            let settings = MandelbrotSettings {
+                width: 16,
+                height: 12,
+                zoom: 3.,
+                offset_x: -0.7,
+                offset_y: 0.2,
+                view_transform,
+                ..MandelbrotSettings::default()
+            };
+            for (x, y, c) in ViewPixels::new(&settings) {
+                assert_eq!(
+                    c,
+                    pixel_to_complex(&settings, x as f32, y as f32),
+                    "{:?}",
+                    view_transform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn panned_counts_match_a_full_render() {
+        // An offset and zoom that don't divide evenly, so the panned offsets round differently each step
+        let mut settings = MandelbrotSettings {
+            width: 40,
+            height: 30,
+            max_iterations: 300,
+            zoom: 3.3,
+            offset_x: -0.7436439,
+            offset_y: 0.1318259,
+            supersample: 2,
+            ..MandelbrotSettings::default()
+        };
+        let (mut counts, _, _) = render_counts(&settings);
+        for (dx, dy) in [(7, 0), (0, -5), (-13, 3), (1, 1), (39, -29)] {
+            let previous = settings.clone();
+            settings.pan_pixels(dx, dy);
+            counts = render_counts_panned(&settings, &previous, &counts, dx, dy);
+            assert_eq!(
+                counts,
+                render_counts(&settings).0,
+                "after panning by {}, {}",
+                dx,
+                dy
+            );
+        }
+    }
+
+    #[test]
+    fn starting_z_at_c_is_one_iteration_ahead_of_starting_at_0() {
+        let from_zero = MandelbrotSettings::default();
+        let from_c = MandelbrotSettings {
+            z0: None,
+            ..MandelbrotSettings::default()
+        };
+        let limit = from_zero.max_iterations;
+        for c in [(-2.1, 0.), (0.5, 0.), (0.3, 0.6), (-0.8, 0.3), (1., 1.)] {
+            let c = Complex::new(c.0, c.1);
+            let zero = count_point(c, &from_zero, limit);
+            assert!(zero < limit as f32, "{} should escape", c);
+            assert_eq!(zero, count_point(c, &from_c, limit) + 1., "at {}", c);
+        }
+        // Inside the set neither start escapes
+        for c in [
+            Complex::new(0., 0.),
+            Complex::new(-1., 0.),
+            Complex::new(-0.1, 0.1),
+        ] {
+            assert_eq!(count_point(c, &from_zero, limit), limit as f32);
+            assert_eq!(count_point(c, &from_c, limit), limit as f32);
+        }
+    }
+
+    #[test]
+    fn c_0_never_escapes_and_c_2_escapes_on_the_second_iteration() {
+        let settings = MandelbrotSettings::default();
+        let limit = settings.max_iterations;
+        assert_eq!(
+            count_point(Complex::new(0., 0.), &settings, limit),
+            limit as f32
+        );
+        // z goes 0, 2, 6: still on the bailout circle after the first step, and past it after the second
+        assert_eq!(count_point(Complex::new(2., 0.), &settings, limit), 2.);
+    }
+
+    #[test]
+    fn the_sample_seed_decides_the_poisson_render() {
+        let seeded = |sample_seed| {
+            render(&MandelbrotSettings {
+                width: 32,
+                height: 24,
+                max_iterations: 100,
+                supersample: 3,
+                sample_pattern: SamplePattern::Poisson,
+                sample_seed,
+                ..MandelbrotSettings::default()
+            })
+        };
+        assert_eq!(seeded(7).as_raw(), seeded(7).as_raw());
+        assert_ne!(seeded(7).as_raw(), seeded(8).as_raw());
+    }
+
+    /// `render_counts` without the row chunks or threads: one plain loop over the pixels.
+    fn serial_counts(settings: &MandelbrotSettings) -> Vec<f32> {
+        ViewPixels::new(settings)
+            .map(|(_, _, c)| count_point(c, settings, settings.max_iterations))
+            .collect()
+    }
+
+    /// Run `work` on a pool of `threads` of its own, as the global pool has just one on a single core machine.
+    #[cfg(feature = "parallel")]
+    fn on_threads<R: Send>(threads: usize, work: impl FnOnce() -> R + Send) -> R {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(work)
+    }
+
+    /// Without the `parallel` feature everything runs on the calling thread anyway.
+    #[cfg(not(feature = "parallel"))]
+    fn on_threads<R>(_threads: usize, work: impl FnOnce() -> R) -> R {
+        work()
+    }
+
+    #[test]
+    fn parallel_renders_match_a_serial_loop() {
+        // Odd sizes, so the rows don't split evenly between threads
+        let settings = MandelbrotSettings {
+            width: 61,
+            height: 37,
+            max_iterations: 400,
+            zoom: 4.,
+            offset_x: -0.74,
+            offset_y: 0.15,
+            colour_mode: ColourMode::Smooth,
+            ..MandelbrotSettings::default()
+        };
+        // Compared as bits, so NaNs match too
+        let bits = |counts: Vec<f32>| counts.into_iter().map(f32::to_bits).collect::<Vec<_>>();
+        let (counts, _, _) = on_threads(4, || render_counts(&settings));
+        assert_eq!(bits(counts), bits(serial_counts(&settings)));
+        assert_eq!(
+            on_threads(4, || render(&settings).into_raw()),
+            on_threads(1, || render(&settings).into_raw())
+        );
+    }
+
+    #[test]
+    fn views_too_deep_for_f32_are_iterated_in_f64() {
+        // Neighbouring pixels are about half an f32 step apart, past where f32 alone could go
+        let settings = MandelbrotSettings {
+            width: 160,
+            height: 120,
+            max_iterations: 400,
+            zoom: 5e5,
+            offset_x: -0.7436439,
+            offset_y: 0.1318259,
+            ..MandelbrotSettings::default()
+        };
+        assert!(settings.needs_f64() && settings.is_resolvable(settings.zoom));
+        let row: Vec<f32> = (0..settings.width)
+            .map(|x| pixel_to_complex(&settings, x as f32, 60.).re)
+            .collect();
+        assert!(
+            row.windows(2).any(|pair| pair[0] == pair[1]),
+            "f32 should round some neighbouring pixels together"
+        );
+
+        let (counts, _, _) = render_counts(&settings);
+        let [width_scale, height_scale] = settings.pixel_scale().map(f64::from);
+        for (index, &count) in counts.iter().enumerate() {
+            let (x, y) = (index as u32 % settings.width, index as u32 / settings.width);
+            let c = Complex::new(
+                (x as f64 - 80.) * width_scale + settings.offset_x as f64,
+                (y as f64 - 60.) * height_scale + settings.offset_y as f64,
+            );
+            let expected = count_point_f64(c, &settings, settings.max_iterations);
+            assert_eq!(count, expected, "at pixel {}, {}", x, y);
+        }
+        let escaped = counts.iter().filter(|&&count| count < 400.).count();
+        assert!(
+            escaped > 0 && escaped < counts.len(),
+            "the view should show an edge of the set"
+        );
+    }
+
+    #[test]
+    fn adjacent_counts_stay_distinct_at_5_million_iterations() {
+        for log_colour in [false, true] {
+            let settings = MandelbrotSettings {
+                max_iterations: 5_000_000,
+                log_colour,
+                ..MandelbrotSettings::default()
+            };
+            for start in [1, 1_000_000, 4_000_000, 4_999_000] {
+                let positions: Vec<f64> = (start..start + 1000)
+                    .map(|count| ramp_position(count as f32, &settings))
+                    .collect();
+                assert!(
+                    positions.windows(2).all(|pair| pair[0] < pair[1]),
+                    "counts from {} collide (log colour {})",
+                    start,
+                    log_colour
+                );
+                assert!(positions.iter().all(|&t| t < 1.));
+            }
+        }
+
+        // Spread over the whole ramp, 5 million counts are more than even 16 bits per channel can show apart, so
+        // the colours only follow every count when the ramp repeats. Every 1000 iterations, neighbouring counts
+        // are a thousandth of the ramp apart all the way up to the limit, in the f32 position and in the pixels
+        let settings = MandelbrotSettings {
+            max_iterations: 5_000_000,
+            colour_period: Some(1000.),
+            ..MandelbrotSettings::default()
+        };
+        for start in [1_000_000, 4_000_000, 4_999_000] {
+            for count in start..start + 999 {
+                let [a, b] = [count, count + 1].map(|count| count as f32);
+                assert!(
+                    colour_position(a, &settings) < colour_position(b, &settings),
+                    "{} and {} collide",
+                    count,
+                    count + 1
+                );
+                assert_ne!(
+                    builtin_colour_16(a, &settings),
+                    builtin_colour_16(b, &settings),
+                    "{} and {} get the same colour",
+                    count,
+                    count + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_sample_pattern_gives_n_squared_samples_inside_the_pixel() {
+        for sample_pattern in SamplePattern::ALL {
+            for supersample in 2..=4 {
+                let settings = MandelbrotSettings {
+                    width: 20,
+                    height: 10,
+                    supersample,
+                    sample_pattern,
+                    sample_seed: 7,
+                    ..MandelbrotSettings::default()
+                };
+                let positions = sample_pattern.positions(supersample, settings.sample_seed);
+                assert_eq!(positions.len(), (supersample * supersample) as usize);
+                for position in &positions {
+                    assert!(
+                        position.iter().all(|p| (0. ..1.).contains(p)),
+                        "{:?} {}x: {:?}",
+                        sample_pattern,
+                        supersample,
+                        position
+                    );
+                }
+                // The offsets move each grid sample to its position, still within the output pixel
+                let [width_scale, height_scale] = settings.supersampled().pixel_scale();
+                let n = supersample as f32;
+                for (k, offset) in sample_offsets(&settings).into_iter().enumerate() {
+                    let (i, j) = (
+                        (k as u32 % supersample) as f32,
+                        (k as u32 / supersample) as f32,
+                    );
+                    let x = (i + offset.re / width_scale) / n;
+                    let y = (j + offset.im / height_scale) / n;
+                    assert!(
+                        near([x, y], positions[k], 1e-4),
+                        "{:?} {}x",
+                        sample_pattern,
+                        n
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn palette_blends_of_0_and_1_give_exactly_either_palette() {
+        let fire = Arc::new(Palette::builtin("fire").unwrap());
+        let ice = Arc::new(Palette::builtin("ice").unwrap());
+        let alone = |palette: &Arc<Palette>| MandelbrotSettings {
+            width: 32,
+            height: 24,
+            colour_mode: ColourMode::Smooth,
+            palette: Some(Arc::clone(palette)),
+            ..MandelbrotSettings::default()
+        };
+        let blended = |palette_blend| MandelbrotSettings {
+            blend_palette: Some(Arc::clone(&ice)),
+            palette_blend,
+            ..alone(&fire)
+        };
+        assert_eq!(render(&blended(0.)), render(&alone(&fire)));
+        assert_eq!(render(&blended(1.)), render(&alone(&ice)));
+        assert_eq!(render_16(&blended(0.)), render_16(&alone(&fire)));
+        assert_eq!(render_16(&blended(1.)), render_16(&alone(&ice)));
+        assert_ne!(render(&blended(0.5)), render(&alone(&fire)));
+    }
+
+    #[test]
+    fn tiled_renders_stitch_into_the_full_render() {
+        // Sizes that don't divide the tile size, so the edge tiles are cut short
+        for (supersample, tile_size) in [(1, 16), (1, 7), (2, 13)] {
+            let settings = MandelbrotSettings {
+                width: 45,
+                height: 34,
+                max_iterations: 300,
+                zoom: 3.3,
+                offset_x: -0.7436439,
+                offset_y: 0.1318259,
+                supersample,
+                downscale_filter: DownscaleFilter::Box,
+                ..MandelbrotSettings::default()
+            };
+            let full = render(&settings);
+            let mut stitched = ImageBuffer::new(settings.width, settings.height);
+            for region in Region::tiles(&settings, tile_size) {
+                let tile = render_region(&settings, region).unwrap();
+                for (x, y, pixel) in tile.enumerate_pixels() {
+                    stitched.put_pixel(region.x0 + x, region.y0 + y, *pixel);
+                }
+            }
+            assert!(
+                stitched == full,
+                "{}x supersampled, {} pixel tiles",
+                supersample,
+                tile_size
+            );
+        }
+    }
+
+    #[test]
+    fn smooth_16_bit_renders_use_more_than_256_levels() {
+        let settings = MandelbrotSettings {
+            width: 96,
+            height: 72,
+            colour_mode: ColourMode::Smooth,
+            ..MandelbrotSettings::default()
+        };
+        let image = render_16(&settings);
+        let mut levels: Vec<u16> = image.pixels().map(|pixel| pixel[0]).collect();
+        levels.sort_unstable();
+        levels.dedup();
+        assert!(levels.len() > 256, "only {} levels", levels.len());
+    }
+
+    #[test]
+    fn each_pixel_class_gets_its_own_colour() {
+        let settings = MandelbrotSettings {
+            max_iterations: 100,
+            interior_colour: Some([10, 20, 30, 255]),
+            degenerate_colour: [0, 255, 0, 255],
+            ..MandelbrotSettings::default()
+        };
+        let classes = [
+            (f32::NAN, PixelClass::Degenerate),
+            (f32::INFINITY, PixelClass::Degenerate),
+            (100., PixelClass::Interior),
+            (40., PixelClass::Escaped),
+        ];
+        for (count, class) in classes {
+            assert_eq!(PixelClass::of(count, &settings), class, "count {}", count);
+        }
+        assert_eq!(builtin_colour(f32::NAN, &settings).0, [0, 255, 0, 255]);
+        assert_eq!(builtin_colour(100., &settings).0, [10, 20, 30, 255]);
+        let escaped = builtin_colour(40., &settings).0;
+        assert_eq!(
+            escaped,
+            ramp_colour(colour_position(40., &settings), &settings)
+        );
+        assert!(escaped != [10, 20, 30, 255] && escaped != [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn the_interior_gets_its_colour_and_the_exterior_never_reaches_it() {
+        for interior_colour in [Some([0, 0, 0, 255]), Some([200, 40, 90, 255]), None] {
+            // The centre pixel is c = 0, deep inside the set
+            let settings = MandelbrotSettings {
+                width: 64,
+                height: 48,
+                offset_x: 0.,
+                offset_y: 0.,
+                interior_colour,
+                ..MandelbrotSettings::default()
+            };
+            let expected = interior_colour.unwrap_or([255, 255, 255, 255]); // The end of the grayscale ramp
+            assert_eq!(render(&settings).get_pixel(32, 24).0, expected);
+            // Even the latest escapes stop short of the interior's colour
+            let limit = settings.max_iterations as f32;
+            for count in [limit - 1., limit - 0.01, limit.next_down()] {
+                assert_ne!(
+                    builtin_colour(count, &settings).0,
+                    expected,
+                    "count {}",
+                    count
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hybrid_precision_matches_f64_where_it_uses_it() {
+        // About 25 f32 steps per pixel, close enough to the limit for f32 to get boundary points wrong
+        let auto = MandelbrotSettings {
+            width: 160,
+            height: 120,
+            max_iterations: 3000,
+            zoom: 1.2e4,
+            offset_x: -0.7436439,
+            offset_y: 0.1318259,
+            f64_region: Some(Region {
+                x0: 60,
+                y0: 40,
+                x1: 100,
+                y1: 80,
+            }),
+            ..MandelbrotSettings::default()
+        };
+        assert!(!auto.needs_f64());
+        let with = |precision| MandelbrotSettings {
+            precision,
+            ..auto.clone()
+        };
+        let f32_counts = render_counts(&auto).0;
+        let f64_counts = render_counts(&with(Precision::Double)).0;
+        let hybrid = render_counts(&with(Precision::Hybrid)).0;
+        let precise = precise_samples(&f32_counts, Region::full(&auto), &auto);
+
+        let mut fixed = 0;
+        for (index, &precise) in precise.iter().enumerate() {
+            let (x, y) = (index as u32 % 160, index as u32 / 160);
+            let expected = if precise {
+                f64_counts[index]
+            } else {
+                f32_counts[index]
+            };
+            assert_eq!(hybrid[index], expected, "at pixel {}, {}", x, y);
+            assert!(precise || !(60..100).contains(&x) || !(40..80).contains(&y));
+            fixed += (precise && f32_counts[index] != f64_counts[index]) as usize;
+        }
+        let share = precise.iter().filter(|&&p| p).count() as f32 / precise.len() as f32;
+        assert!(
+            share < 0.5,
+            "{:.0}% of the samples were iterated again",
+            share * 100.
+        );
+        assert!(fixed > 0, "f32 should have got some of the boundary wrong");
+    }
+
+    #[test]
+    fn the_centres_of_the_first_bulbs_get_their_periods() {
+        // The centre of the main cardioid is a fixed point, the largest bulb's a 2-cycle, the top bulb's a 3-cycle
+        for (c, period) in [((0., 0.), 1), ((-1., 0.), 2), ((-0.1225611, 0.7448618), 3)] {
+            let settings = MandelbrotSettings {
+                width: 1,
+                height: 1,
+                max_iterations: 200,
+                zoom: 1e4, // So the one pixel's coordinate is all but the centre itself
+                offset_x: c.0,
+                offset_y: c.1,
+                interior_mode: InteriorMode::Period,
+                ..MandelbrotSettings::default()
+            };
+            let counts = render_counts(&settings).0;
+            assert_eq!(counts, [(200 + period) as f32], "at {:?}", c);
+            let pixel = render(&settings).get_pixel(0, 0).0;
+            assert_eq!(pixel, PERIOD_COLOURS[period - 1], "at {:?}", c);
+
+            // Without the period only the flat interior count is left
+            let flat = MandelbrotSettings {
+                interior_mode: InteriorMode::Flat,
+                ..settings
+            };
+            assert_eq!(render_counts(&flat).0, [200.]);
+        }
+    }
+}
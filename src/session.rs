@@ -0,0 +1,137 @@
+use mandelbrot_test::flythrough::Waypoint;
+use mandelbrot_test::json::Value;
+use mandelbrot_test::MandelbrotSettings;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Most views the zoom history keeps. Older ones are forgotten first.
+pub const MAX_HISTORY: usize = 100;
+
+/// Everything needed to pick the viewer up where it was left: the view and colouring (colour mode included),
+/// the bookmarks and zoom history, plus the toggles that aren't part of the settings. Saved when the window
+/// closes, when `--session` or `--resume` asks for it, and restored with `--resume`.
+pub struct Session {
+    pub settings: MandelbrotSettings,
+    pub bookmarks: BTreeMap<u8, Waypoint>, // Views saved on the number keys, by their digit
+    pub history: Vec<Waypoint>, // Views zoomed away from, most recent last, for going back
+    pub show_grid: bool,
+    pub use_preview: bool,
+    pub accumulate: bool,
+    pub snap_clicks: bool,
+    pub inertia: bool,
+}
+
+impl Session {
+    pub fn to_json(&self) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert("settings".to_string(), self.settings.to_json());
+        let bookmarks = self
+            .bookmarks
+            .iter()
+            .map(|(digit, view)| (digit.to_string(), view.to_json()))
+            .collect();
+        map.insert("bookmarks".to_string(), Value::Object(bookmarks));
+        let history = self.history.iter().map(|view| view.to_json()).collect();
+        map.insert("history".to_string(), Value::Array(history));
+        map.insert("show_grid".to_string(), Value::Bool(self.show_grid));
+        map.insert("use_preview".to_string(), Value::Bool(self.use_preview));
+        map.insert("accumulate".to_string(), Value::Bool(self.accumulate));
+        map.insert("snap_clicks".to_string(), Value::Bool(self.snap_clicks));
+        map.insert("inertia".to_string(), Value::Bool(self.inertia));
+        Value::Object(map)
+    }
+
+    /// Read a session back. The settings must be complete, but missing toggles take their usual defaults and
+    /// missing bookmarks or history start empty, as in sessions saved before they were kept.
+    pub fn from_json(json: &Value) -> Result<Session, String> {
+        let settings = json.get("settings").ok_or("missing 'settings'")?;
+        let flag =
+            |key: &str, default: bool| json.get(key).and_then(Value::as_bool).unwrap_or(default);
+        let bookmarks = match json.get("bookmarks") {
+            Some(Value::Object(map)) => map
+                .iter()
+                .map(|(digit, view)| {
+                    let slot = digit
+                        .parse()
+                        .ok()
+                        .filter(|slot| (1..=9).contains(slot))
+                        .ok_or_else(|| format!("bookmark '{}' isn't a digit from 1 to 9", digit))?;
+                    let view = Waypoint::from_json(view)
+                        .map_err(|message| format!("bookmark {} {}", digit, message))?;
+                    Ok((slot, view))
+                })
+                .collect::<Result<BTreeMap<u8, Waypoint>, String>>()?,
+            Some(_) => return Err("'bookmarks' must be an object".to_string()),
+            None => BTreeMap::new(),
+        };
+        let history = match json.get("history") {
+            Some(Value::Array(views)) => views
+                .iter()
+                .enumerate()
+                .map(|(index, view)| {
+                    Waypoint::from_json(view)
+                        .map_err(|message| format!("history view {} {}", index + 1, message))
+                })
+                .collect::<Result<Vec<Waypoint>, String>>()?,
+            Some(_) => return Err("'history' must be a list".to_string()),
+            None => Vec::new(),
+        };
+        Ok(Session {
+            settings: MandelbrotSettings::from_json(settings)?,
+            bookmarks,
+            history,
+            show_grid: flag("show_grid", false),
+            use_preview: flag("use_preview", true),
+            accumulate: flag("accumulate", false),
+            snap_clicks: flag("snap_clicks", false),
+            inertia: flag("inertia", true),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Session, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let json = Value::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Session::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json().to_pretty_string())
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mandelbrot_test::render::ColourMode;
+
+    #[test]
+    fn bookmarks_and_history_survive_a_round_trip() {
+        let view = |offset_x, zoom| Waypoint {
+            offset_x,
+            offset_y: 0.25,
+            zoom,
+        };
+        let session = Session {
+            settings: MandelbrotSettings {
+                colour_mode: ColourMode::Potential,
+                ..MandelbrotSettings::default()
+            },
+            bookmarks: BTreeMap::from([(3, view(-0.75, 8.)), (9, view(0.3, 1e4))]),
+            history: vec![view(-0.5, 1.), view(-0.6, 2.)],
+            show_grid: true,
+            use_preview: false,
+            accumulate: false,
+            snap_clicks: true,
+            inertia: false,
+        };
+        let json = Value::parse(&session.to_json().to_pretty_string()).unwrap();
+        let reloaded = Session::from_json(&json).unwrap();
+        assert_eq!(reloaded.bookmarks, session.bookmarks);
+        assert_eq!(reloaded.history, session.history);
+        assert_eq!(reloaded.settings.colour_mode, ColourMode::Potential);
+        assert!(reloaded.show_grid && !reloaded.use_preview && reloaded.snap_clicks);
+        assert!(!reloaded.inertia);
+    }
+}
@@ -0,0 +1,87 @@
+use image::{ImageBuffer, Pixel};
+
+/// How a finished image is folded onto itself, for wallpapers and kaleidoscopes. Each keeps one part of the image
+/// and reflects it over the rest, so the fold lines run through the middle of the image. An image mirrored
+/// left to right also tiles seamlessly side by side, since each edge meets its own reflection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Symmetry {
+    None,       // The image as rendered
+    Horizontal, // The left half mirrored onto the right
+    FourFold, // The top left quarter mirrored onto the other three, so it tiles in both directions
+    EightFold, // The four-fold fold with the quarter also reflected across its diagonal, like a kaleidoscope
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 4] = [
+        Symmetry::None,
+        Symmetry::Horizontal,
+        Symmetry::FourFold,
+        Symmetry::EightFold,
+    ];
+
+    /// Name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Symmetry::None => "none",
+            Symmetry::Horizontal => "horizontal",
+            Symmetry::FourFold => "four-fold",
+            Symmetry::EightFold => "eight-fold",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Symmetry> {
+        Symmetry::ALL
+            .into_iter()
+            .find(|symmetry| symmetry.name() == name)
+    }
+
+    /// Fold `img` in place. Every pixel is copied from the one it reflects, which is in the part that's kept.
+    pub fn apply<P: Pixel>(self, img: &mut ImageBuffer<P, Vec<P::Subpixel>>) {
+        if self == Symmetry::None {
+            return;
+        }
+        let (width, height) = img.dimensions();
+        for y in 0..height {
+            for x in 0..width {
+                let (source_x, source_y) = self.source(x, y, width, height);
+                if (source_x, source_y) != (x, y) {
+                    let pixel = *img.get_pixel(source_x, source_y);
+                    img.put_pixel(x, y, pixel);
+                }
+            }
+        }
+    }
+
+    /// The kept pixel that pixel (x, y) of a `width` x `height` image shows after folding.
+    fn source(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        // Reflect into the left half and, past a horizontal mirror, the top half too
+        let folded_x = x.min(width - 1 - x);
+        let folded_y = match self {
+            Symmetry::None | Symmetry::Horizontal => return (folded_x, y),
+            Symmetry::FourFold | Symmetry::EightFold => y.min(height - 1 - y),
+        };
+        if self == Symmetry::FourFold {
+            return (folded_x, folded_y);
+        }
+
+        // Then across the diagonal through the middle, keeping the side nearer the fold line along the image's
+        // longer side, whose reflection always lands inside the image. Worked in twice the distance from the
+        // middle, which is a whole number of pixels even in an even-sized image
+        let across = (width - 1 - 2 * folded_x) as i64; // Twice the distance left of the vertical fold line
+        let down = (height - 1 - 2 * folded_y) as i64; // And above the horizontal one
+        let kept = if width >= height {
+            down <= across
+        } else {
+            across <= down
+        };
+        if kept {
+            return (folded_x, folded_y);
+        }
+        // Swap the distances. With one of the sides odd and the other even they differ by half a pixel, so
+        // this rounds to the nearer pixel towards the middle
+        (
+            ((width as i64 - 1 - down) / 2) as u32,
+            ((height as i64 - 1 - across) / 2) as u32,
+        )
+    }
+}
@@ -0,0 +1,47 @@
+//! Plain C ABI exports for running the renderer as a wasm module, without any bindings generator. Build with
+//! `cargo build --lib --target wasm32-unknown-unknown --no-default-features --release`, then from JS call
+//! `mandelbrot_render`, copy the pixels out of the module's memory, and hand them back to `mandelbrot_free`.
+
+use crate::mandelbrot_settings::MandelbrotSettings;
+use crate::render::render_rgba;
+
+/// Render the default colouring of a view to a new RGBA buffer of `width * height * 4` bytes, returning a
+/// pointer into the module's memory. Returns null if the size is rejected by `MandelbrotSettings::check_size`.
+#[no_mangle]
+pub extern "C" fn mandelbrot_render(
+    width: u32,
+    height: u32,
+    max_iterations: u32,
+    zoom: f32,
+    offset_x: f32,
+    offset_y: f32,
+) -> *mut u8 {
+    let settings = MandelbrotSettings {
+        width,
+        height,
+        max_iterations: max_iterations.max(1),
+        zoom,
+        offset_x,
+        offset_y,
+        ..MandelbrotSettings::default()
+    };
+    if settings.check_size().is_err() {
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(render_rgba(&settings).into_boxed_slice()) as *mut u8
+}
+
+/// Free a buffer returned by `mandelbrot_render`.
+///
+/// # Safety
+/// `pixels` must have come from `mandelbrot_render` with the same `width` and `height`, and not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mandelbrot_free(pixels: *mut u8, width: u32, height: u32) {
+    if pixels.is_null() {
+        return;
+    }
+    let length = width as usize * height as usize * 4;
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+        pixels, length,
+    )));
+}
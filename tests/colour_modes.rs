@@ -0,0 +1,58 @@
+//! Renders a small fixed view in each colour mode and compares a hash of the pixels with the reference in
+//! `tests/snapshots/colour_modes.txt`, so a change to the counts or colouring that alters the output is caught.
+//! After a deliberate change, rewrite the references with `UPDATE_SNAPSHOTS=1 cargo test --test colour_modes`.
+
+use mandelbrot_test::mandelbrot_settings::MandelbrotSettings;
+use mandelbrot_test::render::{render, ColourMode};
+use std::path::Path;
+
+const SNAPSHOTS: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/snapshots/colour_modes.txt"
+);
+
+/// 64-bit FNV-1a, enough to tell renders apart without pulling in a hashing crate.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The hash of each mode's render, one `name hash` line per mode.
+fn render_hashes() -> String {
+    ColourMode::ALL
+        .into_iter()
+        .map(|mode| {
+            let settings = MandelbrotSettings {
+                width: 64,
+                height: 48,
+                max_iterations: 200,
+                offset_x: -0.5,
+                offset_y: 0.1,
+                colour_mode: mode,
+                ..MandelbrotSettings::default()
+            };
+            format!(
+                "{} {:016x}\n",
+                mode.name(),
+                fnv1a(render(&settings).as_raw())
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn each_colour_mode_matches_its_reference() {
+    let hashes = render_hashes();
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(SNAPSHOTS, &hashes).expect("couldn't write the references");
+        return;
+    }
+    let expected = std::fs::read_to_string(Path::new(SNAPSHOTS)).expect(
+        "no references; create them with UPDATE_SNAPSHOTS=1 cargo test --test colour_modes",
+    );
+    assert_eq!(
+        hashes, expected,
+        "renders differ from the references; if that's intended, rerun with UPDATE_SNAPSHOTS=1"
+    );
+}